@@ -0,0 +1,51 @@
+//! Evaluation of a formula for many values of its variables, walking the tree against
+//! running its bytecode row by row or over columns.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use rust_calculator::bytecode::Bytecode;
+use rust_calculator::eval::{Environment, Eval};
+use rust_calculator::parser::Parser;
+use rust_calculator::token::tokenize;
+
+const FORMULA: &str = "3x**2 - 2x*y + sin(x) * exp(-y / 4) + sqrt(x*x + y*y) / (1 + abs(y))";
+
+fn bench_eval(c: &mut Criterion) {
+    let expr = Parser::new(tokenize(FORMULA).unwrap()).parse().unwrap();
+    let bytecode = Bytecode::compile(&expr, &["x", "y"]).unwrap();
+    let points = (0..1000)
+        .map(|i| (i as f64 / 100., (1000 - i) as f64 / 250.))
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("1000 points");
+    group.bench_function("tree", |b| {
+        let mut env = Environment::new();
+        b.iter(|| {
+            for &(x, y) in &points {
+                env.set("x", x);
+                env.set("y", y);
+                black_box(expr.eval_in(&env).unwrap());
+            }
+        })
+    });
+    group.bench_function("bytecode", |b| {
+        b.iter(|| {
+            for &(x, y) in &points {
+                black_box(bytecode.eval_in(&[x, y]).unwrap());
+            }
+        })
+    });
+    group.bench_function("columns", |b| {
+        let xs = points.iter().map(|&(x, _)| x).collect::<Vec<_>>();
+        let ys = points.iter().map(|&(_, y)| y).collect::<Vec<_>>();
+        let mut output = vec![0.; points.len()];
+        b.iter(|| {
+            bytecode.eval_columns(&[&xs, &ys], &mut output).unwrap();
+            black_box(&output);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_eval);
+criterion_main!(benches);
@@ -1,34 +1,468 @@
 //! # AST module
 //! Contains the structures used to represent the grammar.
+//!
+//! Expressions are displayed in infix notation with only the parentheses needed by
+//! the precedence and associativity of the operators, see [Token#op_prec] and
+//! [Token#is_left_assoc]. Parsing the text of an expression built by the parser gives
+//! it back, as long as its numbers are displayed as valid literals.
+//!
+//! With the `serde` feature, expressions are serialized with the snake case name of
+//! their variant in `type` and its fields in `value`, `1 + x` becomes:
+//! ```json
+//! {"type": "bin_op", "value": [
+//!     {"type": "number", "value": 1.0}, "add", {"type": "variable", "value": "x"}
+//! ]}
+//! ```
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("((1+2))*-(3)**2x").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! assert_eq!(expr.to_string(), "(1 + 2) * -3 ** 2 * x");
+//! # }
+//! ```
 
+use std::fmt::{Display, Formatter};
+
+use crate::token::Token;
+
+/// An expression over numbers of type `N`, see [Numeric](crate::numeric::Numeric).
 #[derive(PartialEq, Clone, Debug)]
-pub enum Expr {
-    BinOp(Box<Expr>, BinOpType, Box<Expr>),
-    UnaryOp(UnaryOpType, Box<Expr>),
-    Number(Number),
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Expr<N = Number> {
+    BinOp(Box<Expr<N>>, BinOpType, Box<Expr<N>>),
+    UnaryOp(UnaryOpType, Box<Expr<N>>),
+    Number(N),
+    Variable(String),
+    Call(String, Vec<Expr<N>>),
     E,
     Pi,
+    /// The imaginary unit.
+    I,
+    /// A number of the unit with the given name.
+    Quantity(N, String),
+    /// The conversion of the left expression to the unit of the right one.
+    Convert(Box<Expr<N>>, Box<Expr<N>>),
+    /// The second expression if the first one is true, that is not zero, the third one
+    /// otherwise.
+    Conditional(Box<Expr<N>>, Box<Expr<N>>, Box<Expr<N>>),
 }
 
-impl Expr {
+impl<N> Expr<N> {
     pub fn boxed(self) -> Box<Self> {
         Box::new(self)
     }
+
+    /// Number of nodes of the expression, itself included.
+    pub fn size(&self) -> usize {
+        1 + match self {
+            Self::BinOp(left, _, right) | Self::Convert(left, right) => left.size() + right.size(),
+            Self::UnaryOp(_, operand) => operand.size(),
+            Self::Call(_, args) => args.iter().map(Self::size).sum(),
+            Self::Conditional(condition, then, otherwise) => {
+                condition.size() + then.size() + otherwise.size()
+            }
+            _ => 0,
+        }
+    }
+
+    /// The operand of a percentage `p%`, relative to the left operand when it is added
+    /// or subtracted: `x + 10%` is `x * 110 / 100`.
+    pub fn percentage(&self) -> Option<&Expr<N>> {
+        match self {
+            Self::UnaryOp(UnaryOpType::Percent, operand) => Some(operand),
+            _ => None,
+        }
+    }
+
+    /// Precedence of the loosest prefix operator in the chain starting the expression,
+    /// like the `not` of `-not x`, which takes every operator following it.
+    fn prefix_prec(&self) -> Option<u32> {
+        match self {
+            Self::UnaryOp(op, operand) if !op.is_postfix() => {
+                let prec = op.token().op_prec();
+                Some(operand.prefix_prec().map_or(prec, |inner| inner.min(prec)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<N: Display> Display for Expr<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(num) => write!(f, "{}", num),
+            Self::Variable(name) => write!(f, "{}", name),
+            Self::E => write!(f, "e"),
+            Self::Pi => write!(f, "pi"),
+            Self::I => write!(f, "i"),
+            Self::Quantity(num, unit) => write!(f, "{} {}", num, unit),
+            Self::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (index, arg) in args.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            // Postfix operators bind tighter than every prefix or binary one
+            Self::UnaryOp(op, operand) if op.is_postfix() => {
+                let parens = match **operand {
+                    Self::UnaryOp(operand_op, _) => !operand_op.is_postfix(),
+                    Self::BinOp(..) | Self::Convert(..) | Self::Conditional(..) => true,
+                    _ => false,
+                };
+                write_operand(f, operand, parens)?;
+                write!(f, "{}", op.symbol())
+            }
+            // Parenthesized even when `not` binds looser than the operator of its operand
+            Self::UnaryOp(op, operand) => {
+                match op {
+                    UnaryOpType::Not => write!(f, "not ")?,
+                    _ => write!(f, "{}", op.symbol())?,
+                }
+                write_operand(
+                    f,
+                    operand,
+                    matches!(
+                        **operand,
+                        Self::BinOp(..) | Self::Convert(..) | Self::Conditional(..)
+                    ),
+                )
+            }
+            Self::BinOp(left, op, right) => {
+                let token = op.token();
+
+                // The left operator is applied first only if the parser pops it when
+                // reading `op`, and the right one only if it doesn't pop `op`
+                let left_parens = match **left {
+                    Self::Convert(..) | Self::Conditional(..) => true,
+                    Self::UnaryOp(..) => left
+                        .prefix_prec()
+                        .is_some_and(|prec| prec < token.op_prec()),
+                    Self::BinOp(_, left_op, _) => {
+                        let left_token = left_op.token();
+                        left_token.op_prec() < token.op_prec()
+                            || (left_token.op_prec() == token.op_prec() && !token.is_left_assoc())
+                    }
+                    _ => false,
+                };
+                let right_parens = match **right {
+                    Self::Convert(..) | Self::Conditional(..) => true,
+                    // A `not` would also take the operators following this expression
+                    Self::UnaryOp(..) => right
+                        .prefix_prec()
+                        .is_some_and(|prec| prec < token.op_prec()),
+                    Self::BinOp(_, right_op, _) => {
+                        let right_token = right_op.token();
+                        token.op_prec() > right_token.op_prec()
+                            || (token.op_prec() == right_token.op_prec()
+                                && right_token.is_left_assoc())
+                    }
+                    _ => false,
+                };
+
+                write_operand(f, left, left_parens)?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, right, right_parens)
+            }
+            // The conversion has the lowest precedence and is left associative
+            Self::Convert(value, target) => {
+                write!(f, "{} in ", value)?;
+                write_operand(f, target, matches!(**target, Self::Convert(..)))
+            }
+            // The conditional binds looser than every operator but the conversion, and
+            // is right associative
+            Self::Conditional(condition, then, otherwise) => {
+                let condition_parens =
+                    matches!(**condition, Self::Convert(..) | Self::Conditional(..));
+                write_operand(f, condition, condition_parens)?;
+                write!(f, " ? ")?;
+                write_operand(f, then, matches!(**then, Self::Convert(..)))?;
+                write!(f, " : ")?;
+                write_operand(f, otherwise, matches!(**otherwise, Self::Convert(..)))
+            }
+        }
+    }
+}
+
+fn write_operand<N: Display>(
+    f: &mut Formatter<'_>,
+    operand: &Expr<N>,
+    parens: bool,
+) -> std::fmt::Result {
+    if parens {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
 }
 
 #[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum BinOpType {
     Add,
     Sub,
     Mul,
     Div,
+    /// The remainder of the floor division, of the sign of the divisor.
+    Mod,
+    FloorDiv,
     Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinOpType {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::FloorDiv => "//",
+            Self::Pow => "**",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::And => "and",
+            Self::Or => "or",
+        }
+    }
+
+    /// Whether the operator is a comparison or a logical operator, giving 1 if true and
+    /// 0 otherwise.
+    pub fn is_boolean(&self) -> bool {
+        matches!(
+            self,
+            Self::Eq | Self::Ne | Self::Lt | Self::Le | Self::Gt | Self::Ge | Self::And | Self::Or
+        )
+    }
+
+    /// Token of the operator, holding its precedence and associativity.
+    pub fn token(&self) -> Token {
+        match self {
+            Self::Add => Token::Plus,
+            Self::Sub => Token::Minus,
+            Self::Mul => Token::Times,
+            Self::Div => Token::Slash,
+            Self::Mod => Token::Percent,
+            Self::FloorDiv => Token::SlashSlash,
+            Self::Pow => Token::TimesTimes,
+            Self::Eq => Token::EqualEqual,
+            Self::Ne => Token::NotEqual,
+            Self::Lt => Token::Less,
+            Self::Le => Token::LessEqual,
+            Self::Gt => Token::Greater,
+            Self::Ge => Token::GreaterEqual,
+            Self::And => Token::And,
+            Self::Or => Token::Or,
+        }
+    }
 }
 
 #[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum UnaryOpType {
     Negate,
     Noop,
+    /// 1 if the operand is zero, 0 otherwise.
+    Not,
+    Factorial,
+    /// The operand divided by 100, see [Expr#percentage].
+    Percent,
 }
 
-pub type Number = f32;
+impl UnaryOpType {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Negate => "-",
+            Self::Noop => "+",
+            Self::Not => "not",
+            Self::Factorial => "!",
+            Self::Percent => "%",
+        }
+    }
+
+    /// Whether the operator follows its operand.
+    pub fn is_postfix(&self) -> bool {
+        matches!(self, Self::Factorial | Self::Percent)
+    }
+
+    /// Token of the operator, holding its precedence.
+    pub fn token(&self) -> Token {
+        match self {
+            Self::Negate => Token::UnaryMinus,
+            Self::Noop => Token::UnaryPlus,
+            Self::Not => Token::Not,
+            Self::Factorial => Token::Bang,
+            Self::Percent => Token::PostfixPercent,
+        }
+    }
+}
+
+/// Default number type.
+pub type Number = f64;
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{BinOpType, Expr, UnaryOpType};
+    use crate::parser::Parser;
+    use crate::token::tokenize;
+
+    fn parse(source: &str) -> Expr {
+        Parser::new(tokenize(source).unwrap()).parse().unwrap()
+    }
+
+    #[test]
+    fn display_minimal_parens() {
+        let cases = [
+            ("1+2*3", "1 + 2 * 3"),
+            ("(1+2)*3", "(1 + 2) * 3"),
+            ("1-(2-3)", "1 - (2 - 3)"),
+            ("(1-2)-3", "1 - 2 - 3"),
+            ("2**3**2", "2 ** 3 ** 2"),
+            ("(2**3)**2", "(2 ** 3) ** 2"),
+            ("-2**2", "-2 ** 2"),
+            ("-(2**2)", "-(2 ** 2)"),
+            ("2**-x", "2 ** -x"),
+            ("--+1", "--+1"),
+            ("2pi(e+i)", "2 * pi * (e + i)"),
+            ("max(1, -x, f((y)))", "max(1, -x, f(y))"),
+            ("1.5", "1.5"),
+            ("3km/20 min in m/s", "3 km / 20 min in m / s"),
+            ("-(1 to m) * 2", "-(1 in m) * 2"),
+            ("1 in (m in s)", "1 in (m in s)"),
+            ("(a<b)==(c>=d) and not(x)", "a < b == (c >= d) and not x"),
+            ("not (a or b) and c", "not (a or b) and c"),
+            ("(not a) + 1", "(not a) + 1"),
+            ("-(not a)", "-not a"),
+            ("a ? b ? c : d : (e ? f : g)", "a ? b ? c : d : e ? f : g"),
+            ("(a ? b : c) ? d : e", "(a ? b : c) ? d : e"),
+            ("1 + (a ? b : c)", "1 + (a ? b : c)"),
+            ("a ? 1 km : 2 km in m", "a ? 1 km : 2 km in m"),
+            ("(-3)! + -3!", "(-3)! + -3!"),
+            ("(3!)!%", "3!!%"),
+            ("x + (x + 1)%", "x + (x + 1)%"),
+            ("a % (-b) // c", "a % -b // c"),
+            ("a % (b // c)", "a % (b // c)"),
+            ("(a / b) / c // d", "a / b / c // d"),
+            ("a / (b % c)", "a / (b % c)"),
+        ];
+
+        for (source, printed) in cases.iter() {
+            assert_eq!(parse(source).to_string(), *printed);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_layout() {
+        let expr = parse("-f(1, pi) + x");
+        let json = serde_json::json!({
+            "type": "bin_op",
+            "value": [
+                {
+                    "type": "unary_op",
+                    "value": ["negate", {
+                        "type": "call",
+                        "value": ["f", [{ "type": "number", "value": 1.0 }, { "type": "pi" }]],
+                    }],
+                },
+                "add",
+                { "type": "variable", "value": "x" },
+            ],
+        });
+
+        assert_eq!(serde_json::to_value(&expr).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Expr>(json).unwrap(), expr);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let mut exprs: Vec<Expr> = vec![Expr::Number(1.), Expr::Variable("x".into())];
+        let ops = [
+            BinOpType::Add,
+            BinOpType::Sub,
+            BinOpType::Mul,
+            BinOpType::Div,
+            BinOpType::Pow,
+            BinOpType::Mod,
+            BinOpType::Lt,
+            BinOpType::And,
+        ];
+
+        // Every expression up to two operators deep
+        for _ in 0..2 {
+            let mut deeper = exprs.clone();
+            for left in &exprs {
+                for op in &ops {
+                    for right in &exprs {
+                        deeper.push(Expr::BinOp(
+                            left.clone().boxed(),
+                            *op,
+                            right.clone().boxed(),
+                        ));
+                    }
+                }
+                deeper.push(Expr::UnaryOp(UnaryOpType::Negate, left.clone().boxed()));
+                deeper.push(Expr::UnaryOp(UnaryOpType::Noop, left.clone().boxed()));
+                deeper.push(Expr::UnaryOp(UnaryOpType::Not, left.clone().boxed()));
+                deeper.push(Expr::UnaryOp(UnaryOpType::Factorial, left.clone().boxed()));
+                deeper.push(Expr::UnaryOp(UnaryOpType::Percent, left.clone().boxed()));
+            }
+            exprs = deeper;
+        }
+
+        // A `not` deeper in a chain of prefix operators
+        let not = Expr::UnaryOp(
+            UnaryOpType::Negate,
+            Expr::UnaryOp(UnaryOpType::Not, Expr::Variable("a".into()).boxed()).boxed(),
+        );
+        let number = |value| Expr::Number(value).boxed();
+        let chains = [
+            Expr::BinOp(not.clone().boxed(), BinOpType::Add, number(1.)),
+            Expr::BinOp(
+                Expr::BinOp(number(2.), BinOpType::Pow, not.boxed()).boxed(),
+                BinOpType::Mul,
+                number(3.),
+            ),
+        ];
+        assert_eq!(chains[0].to_string(), "(-not a) + 1");
+        assert_eq!(chains[1].to_string(), "2 ** (-not a) * 3");
+        exprs.extend(chains);
+
+        for expr in exprs {
+            assert_eq!(parse(&expr.to_string()), expr, "printed as {}", expr);
+        }
+    }
+}
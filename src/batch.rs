@@ -0,0 +1,100 @@
+//! # Batch module
+//! Evaluate every line of a file or of the standard input independently.
+
+use std::io::{self, BufRead, Write};
+
+use rust_calculator::diagnostic::Diagnostic;
+use rust_calculator::numeric::Numeric;
+
+use crate::evaluate;
+use crate::json::report;
+
+/// Outcome of a batch evaluation.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Number of non-blank lines.
+    pub evaluated: usize,
+    pub failed: usize,
+}
+
+/// Evaluate each line of `input` and write its result or error on the same line of
+/// `output`, or as a JSON object if `json` is set. Blank lines are copied as is.
+pub fn run<N: Numeric>(
+    input: impl BufRead,
+    mut output: impl Write,
+    json: bool,
+) -> io::Result<Summary> {
+    let mut summary = Summary::default();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            writeln!(output)?;
+            continue;
+        }
+
+        summary.evaluated += 1;
+        let outcome = evaluate::<N>(&line);
+        if outcome.is_err() {
+            summary.failed += 1;
+        }
+
+        if json {
+            writeln!(output, "{}", report(&line, Some(index + 1), &outcome))?;
+            continue;
+        }
+
+        match outcome {
+            Ok(result) => writeln!(output, "{}", result)?,
+            Err(e) => {
+                let diagnostic = Diagnostic::from_error(&line, &e).with_first_line(index + 1);
+                writeln!(output, "{}", diagnostic.to_short_string())?;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::{run, Summary};
+
+    #[test]
+    fn evaluate_lines() {
+        let input = "1 + 2\n\n(1 + 2))\n2 ** 10\nsqrt(-1)\n";
+        let mut output = Vec::new();
+
+        let summary = run::<f64>(input.as_bytes(), &mut output, false).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                evaluated: 4,
+                failed: 2
+            }
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "3\n",
+                "\n",
+                "3:8: error: Mismatched parenthesis !\n",
+                "1024\n",
+                "5:1: error: Argument out of the domain of 'sqrt'\n",
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_lines_as_json() {
+        let mut output = Vec::new();
+
+        run::<f64>("1 + 2\n\n1 +\n".as_bytes(), &mut output, true).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains(r#""line":1"#) && lines[0].contains(r#""result":3.0"#));
+        assert_eq!(lines[1], "");
+        assert!(lines[2].contains(r#""kind":"not_enough_operands""#));
+    }
+}
@@ -0,0 +1,432 @@
+//! # Bytecode module
+//! Contains a compiler from [Expr] to a flat list of [Instruction]s for a stack machine,
+//! faster than walking the tree when the same expression is evaluated many times.
+//!
+//! Variables are resolved once during the compilation to slots, indices in the slice of
+//! values given to [Bytecode#eval_in], and functions to their [Builtin]. Evaluating the
+//! bytecode gives the same results and errors as [Eval](crate::eval::Eval), except that
+//! unbound variables, unknown functions and wrong arities are reported by the compiler.
+//! Units aren't supported, names that aren't slots are unbound variables.
+//!
+//! Conditionals, `and` and `or` are compiled to jumps so that only the branch chosen is
+//! evaluated, see [Instruction::Branch].
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::bytecode::Bytecode;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("2x + y").unwrap()).parse().unwrap();
+//! let bytecode = Bytecode::compile(&expr, &["x", "y"]).unwrap();
+//!
+//! for x in 0..3 {
+//!     assert_eq!(bytecode.eval_in(&[x as f64, 1.]), Ok(2. * x as f64 + 1.));
+//! }
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{BinOpType, Expr, Number, UnaryOpType};
+use crate::errors::{EvalError, EvalResult};
+use crate::eval::{
+    apply_binary, apply_builtin, check_finite, factorial, relative_percentage, resolve_builtin,
+    Builtin,
+};
+use crate::numeric::Numeric;
+
+/// An operation of the stack machine.
+#[derive(Clone, Debug)]
+pub enum Instruction<N = Number> {
+    /// Push a number.
+    Push(N),
+    /// Push the value of a slot.
+    Load(usize),
+    /// Negate the top of the stack.
+    Negate,
+    /// Replace the top of the stack by its factorial.
+    Factorial,
+    /// Replace the two values on top of the stack by the result of the operator, the
+    /// right operand being on top.
+    BinOp(BinOpType),
+    /// Replace the given number of values on top of the stack by the result of the
+    /// function, the last argument being on top.
+    Call(&'static Builtin, usize),
+    /// Pop the condition on top of the stack and continue at the given instruction if
+    /// it is zero. A conditional is compiled to `condition Branch then Jump else Select`.
+    Branch(usize),
+    /// Continue at the given instruction.
+    Jump(usize),
+    /// No-op when running the jumps, replace the condition and both branches on top of
+    /// the stack by the branch chosen when running every instruction, as
+    /// [eval_columns](Bytecode#method.eval_columns) does.
+    Select,
+}
+
+impl<N: Display> Display for Instruction<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Push(num) => write!(f, "push {}", num),
+            Self::Load(slot) => write!(f, "load {}", slot),
+            Self::Negate => write!(f, "neg"),
+            Self::Factorial => write!(f, "fact"),
+            Self::BinOp(op) => write!(f, "{}", op.symbol()),
+            Self::Call(builtin, count) => write!(f, "call {} {}", builtin.name, count),
+            Self::Branch(target) => write!(f, "branch {}", target),
+            Self::Jump(target) => write!(f, "jump {}", target),
+            Self::Select => write!(f, "select"),
+        }
+    }
+}
+
+/// A compiled expression.
+#[derive(Clone, Debug)]
+pub struct Bytecode<N = Number> {
+    instructions: Vec<Instruction<N>>,
+    /// Name of the variable held by each slot.
+    slots: Vec<String>,
+    /// Largest number of values on the stack during an evaluation.
+    stack_size: usize,
+}
+
+impl<N: Numeric> Bytecode<N> {
+    /// Compile `expr`, the variables it uses must be in `slots`, their position being
+    /// the index of their value during the evaluation.
+    pub fn compile(expr: &Expr<N>, slots: &[&str]) -> EvalResult<Self> {
+        let mut bytecode = Self {
+            instructions: Vec::new(),
+            slots: slots.iter().map(|&slot| slot.into()).collect(),
+            stack_size: 0,
+        };
+        bytecode.emit(expr, 0)?;
+
+        Ok(bytecode)
+    }
+
+    /// Append the instructions of `expr`, with `depth` values already on the stack.
+    fn emit(&mut self, expr: &Expr<N>, depth: usize) -> EvalResult<()> {
+        self.stack_size = self.stack_size.max(depth + 1);
+
+        let instruction = match expr {
+            Expr::Number(num) => Instruction::Push(num.clone()),
+            Expr::E => Instruction::Push(N::e()),
+            Expr::Pi => Instruction::Push(N::pi()),
+            Expr::I => Instruction::Push(N::i().ok_or(EvalError::NotComplex)?),
+            Expr::Variable(name) => Instruction::Load(
+                self.slots
+                    .iter()
+                    .position(|slot| slot == name)
+                    .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?,
+            ),
+            Expr::UnaryOp(UnaryOpType::Noop, operand) => return self.emit(operand, depth),
+            Expr::UnaryOp(UnaryOpType::Negate, operand) => {
+                self.emit(operand, depth)?;
+                Instruction::Negate
+            }
+            Expr::UnaryOp(UnaryOpType::Factorial, operand) => {
+                self.emit(operand, depth)?;
+                Instruction::Factorial
+            }
+            Expr::UnaryOp(UnaryOpType::Percent, operand) => {
+                self.emit(operand, depth)?;
+                self.emit(&Expr::Number(N::from_literal("100")), depth + 1)?;
+                Instruction::BinOp(BinOpType::Div)
+            }
+            Expr::BinOp(left, op @ (BinOpType::Add | BinOpType::Sub), right)
+                if right.percentage().is_some() =>
+            {
+                let percent = right.percentage().unwrap();
+                return self.emit(&relative_percentage(left, *op, percent), depth);
+            }
+            Expr::UnaryOp(UnaryOpType::Not, operand) => {
+                self.emit(operand, depth)?;
+                self.emit(&Expr::Number(N::from_literal("0")), depth + 1)?;
+                Instruction::BinOp(BinOpType::Eq)
+            }
+            // Short-circuit with a conditional, the right operand becoming 1 or 0
+            Expr::BinOp(left, op @ (BinOpType::And | BinOpType::Or), right) => {
+                let zero = Expr::Number(N::from_literal("0")).boxed();
+                let right = Expr::BinOp(right.clone(), BinOpType::Ne, zero.clone()).boxed();
+                let conditional = match op {
+                    BinOpType::And => Expr::Conditional(left.clone(), right, zero),
+                    _ => {
+                        let one = Expr::Number(N::from_literal("1")).boxed();
+                        Expr::Conditional(left.clone(), one, right)
+                    }
+                };
+                return self.emit(&conditional, depth);
+            }
+            Expr::BinOp(left, op, right) => {
+                self.emit(left, depth)?;
+                self.emit(right, depth + 1)?;
+                Instruction::BinOp(*op)
+            }
+            Expr::Quantity(_, unit) => return Err(EvalError::UnsupportedUnit(unit.clone())),
+            Expr::Convert(_, target) => return Err(EvalError::UnsupportedUnit(target.to_string())),
+            Expr::Call(name, args) => {
+                let builtin = resolve_builtin(name, args.len())?;
+                for (index, arg) in args.iter().enumerate() {
+                    self.emit(arg, depth + index)?;
+                }
+                Instruction::Call(builtin, args.len())
+            }
+            // The branches are above the condition when every instruction is run
+            Expr::Conditional(condition, then, otherwise) => {
+                self.emit(condition, depth)?;
+                let branch = self.instructions.len();
+                self.instructions.push(Instruction::Branch(0));
+                self.emit(then, depth + 1)?;
+                let jump = self.instructions.len();
+                self.instructions.push(Instruction::Jump(0));
+
+                self.instructions[branch] = Instruction::Branch(jump + 1);
+                self.emit(otherwise, depth + 2)?;
+                self.instructions[jump] = Instruction::Jump(self.instructions.len());
+                Instruction::Select
+            }
+        };
+        self.instructions.push(instruction);
+
+        Ok(())
+    }
+
+    pub fn instructions(&self) -> &[Instruction<N>] {
+        &self.instructions
+    }
+
+    /// Largest number of values on the stack during an evaluation.
+    pub fn stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    /// Name of the variable of each slot.
+    pub fn slots(&self) -> &[String] {
+        &self.slots
+    }
+
+    /// Same as [Eval#eval](crate::eval::Eval#tymethod.eval) with the values of the slots,
+    /// the missing ones evaluate to NaN.
+    pub fn eval(&self, values: &[N]) -> N {
+        self.run(values, false)
+            .unwrap_or_else(|_| N::from_f64(f64::NAN))
+    }
+
+    /// Same as [Eval#eval_in](crate::eval::Eval#tymethod.eval_in) with the values of
+    /// the slots.
+    pub fn eval_in(&self, values: &[N]) -> EvalResult<N> {
+        self.run(values, true)
+    }
+
+    fn run(&self, values: &[N], checked: bool) -> EvalResult<N> {
+        if let Some(missing) = self.slots.get(values.len()) {
+            return Err(EvalError::UnboundVariable(missing.clone()));
+        }
+
+        // The compiler guarantees that the operands of each instruction are on the stack
+        let mut stack = Vec::with_capacity(self.stack_size);
+        let mut next = 0;
+        while let Some(instruction) = self.instructions.get(next) {
+            next += 1;
+            match instruction {
+                Instruction::Push(num) => stack.push(check_finite(num.clone(), checked)?),
+                Instruction::Load(slot) => {
+                    stack.push(check_finite(values[*slot].clone(), checked)?)
+                }
+                Instruction::Negate => {
+                    let operand = stack.pop().unwrap();
+                    stack.push(-operand);
+                }
+                Instruction::Factorial => {
+                    let operand = stack.pop().unwrap();
+                    stack.push(factorial(operand, checked)?);
+                }
+                Instruction::BinOp(op) => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.pop().unwrap();
+                    stack.push(apply_binary(*op, left, right, checked)?);
+                }
+                Instruction::Call(builtin, count) => {
+                    let start = stack.len() - count;
+                    let result = apply_builtin(builtin, &stack[start..], checked)?;
+                    stack.truncate(start);
+                    stack.push(result);
+                }
+                Instruction::Branch(otherwise) => {
+                    if stack.pop().unwrap().is_zero() {
+                        next = *otherwise;
+                    }
+                }
+                Instruction::Jump(target) => next = *target,
+                Instruction::Select => {}
+            }
+        }
+
+        Ok(stack.pop().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Expr;
+    use crate::bytecode::Bytecode;
+    use crate::complex::Complex;
+    use crate::errors::EvalError;
+    use crate::eval::{Arity, Environment, Eval};
+    use crate::parser::Parser;
+    use crate::token::{tokenize, tokenize_as};
+
+    fn parse(source: &str) -> Expr {
+        Parser::new(tokenize(source).unwrap()).parse().unwrap()
+    }
+
+    fn compile(source: &str) -> Result<Bytecode, EvalError> {
+        Bytecode::compile(&parse(source), &["x", "y"])
+    }
+
+    #[test]
+    fn compile_instructions() {
+        let bytecode = compile("-(x + 2) * max(y, +1, 3)").unwrap();
+        let listing = bytecode
+            .instructions()
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            listing,
+            vec![
+                "load 0",
+                "push 2",
+                "+",
+                "neg",
+                "load 1",
+                "push 1",
+                "push 3",
+                "call max 3",
+                "*"
+            ]
+        );
+        assert_eq!(bytecode.stack_size, 4);
+        assert_eq!(bytecode.slots(), ["x", "y"]);
+    }
+
+    #[test]
+    fn compile_conditionals() {
+        let bytecode = compile("x > 0 and y ? x : -x").unwrap();
+        let listing = bytecode
+            .instructions()
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            listing,
+            vec![
+                "load 0",
+                "push 0",
+                ">",
+                "branch 8",
+                "load 1",
+                "push 0",
+                "!=",
+                "jump 9",
+                "push 0",
+                "select",
+                "branch 13",
+                "load 0",
+                "jump 15",
+                "load 0",
+                "neg",
+                "select"
+            ]
+        );
+        assert_eq!(bytecode.stack_size, 3);
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert_eq!(
+            compile("x + z").unwrap_err(),
+            EvalError::UnboundVariable("z".into())
+        );
+        assert_eq!(
+            compile("f(x)").unwrap_err(),
+            EvalError::UnknownFunction("f".into())
+        );
+        assert_eq!(
+            compile("atan2(x)").unwrap_err(),
+            EvalError::WrongArity {
+                name: "atan2".into(),
+                expected: Arity::exactly(2),
+                got: 1
+            }
+        );
+        assert_eq!(compile("2i").unwrap_err(), EvalError::NotComplex);
+        assert_eq!(
+            compile("2 km").unwrap_err(),
+            EvalError::UnsupportedUnit("km".into())
+        );
+        assert_eq!(
+            compile("x in m").unwrap_err(),
+            EvalError::UnsupportedUnit("m".into())
+        );
+    }
+
+    #[test]
+    fn same_as_tree_walker() {
+        let sources = [
+            "2x + y",
+            "x ** y ** 2 / 3",
+            "-sin(x) * cos(y) + atan2(y, x)",
+            "log(x ** 2 + 1, 3) - min(x, y, 0)",
+            "1 / (x - x)",
+            "sqrt(y - 10)",
+            "x ** 2000",
+            "pi * e",
+            "x > 0 ? sqrt(x) : y < 0 and 1 / y",
+            "not x or ln(x) >= y",
+            "x == -3 != (y <= 7)",
+            "x % y + x // y - y % 0.7",
+            "x! * y% - (x + 5)!",
+            "x - 10% + (y + 5%) % 3",
+        ];
+
+        for source in sources.iter() {
+            let expr = parse(source);
+            let bytecode = Bytecode::compile(&expr, &["x", "y"]).unwrap();
+
+            for &(x, y) in [(0., 0.), (1.5, -2.), (-3., 7.), (10., 0.5)].iter() {
+                let env = vec![("x", x), ("y", y)]
+                    .into_iter()
+                    .collect::<Environment>();
+                assert_eq!(bytecode.eval_in(&[x, y]), expr.eval_in(&env), "{}", source);
+            }
+        }
+
+        let bytecode = compile("x + y").unwrap();
+        assert_eq!(
+            bytecode.eval_in(&[1.]),
+            Err(EvalError::UnboundVariable("y".into()))
+        );
+        assert!(bytecode.eval(&[1.]).is_nan());
+        assert_eq!(
+            compile("1 / (x - x)").unwrap().eval(&[1., 0.]),
+            f64::INFINITY
+        );
+        assert_eq!(bytecode.eval_in(&[f64::NAN, 1.]), Err(EvalError::NonFinite));
+    }
+
+    #[test]
+    fn generic_numbers() {
+        let expr = Parser::new(tokenize_as::<Complex>("sqrt(x) + i").unwrap())
+            .parse()
+            .unwrap();
+        let bytecode = Bytecode::compile(&expr, &["x"]).unwrap();
+
+        assert_eq!(
+            bytecode.eval_in(&[Complex::new(-4., 0.)]),
+            Ok(Complex::new(0., 3.))
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! # Columns module
+//! Contains the evaluation of an expression over columns of values, one per variable,
+//! much faster than evaluating it row by row.
+//!
+//! The [Bytecode] of the expression is run on chunks of rows at once, each instruction
+//! being a loop over the chunk that the compiler can vectorise. The evaluation follows
+//! IEEE 754 semantics like [Eval#eval](crate::eval::Eval#tymethod.eval), rows outside
+//! of the domain of an operation evaluate to NaN instead of failing the whole column.
+//! Both branches of a conditional are evaluated for every row, the condition choosing
+//! the result of each one.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::columns::eval_columns;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("x * y + 1").unwrap()).parse().unwrap();
+//!
+//! let mut output = [0.; 3];
+//! eval_columns(&expr, &[("x", &[1., 2., 3.]), ("y", &[4., 5., 6.])], &mut output).unwrap();
+//! assert_eq!(output, [5., 11., 19.]);
+//! # }
+//! ```
+
+use crate::ast::{BinOpType, Expr, Number};
+use crate::bytecode::{Bytecode, Instruction};
+use crate::errors::{EvalError, EvalResult};
+use crate::eval::factorial;
+
+/// Number of rows evaluated by each instruction.
+const CHUNK: usize = 256;
+
+/// Evaluate `expr` for each row of `columns`, named after the variables they hold, into
+/// the same row of `output`.
+///
+/// # Panics
+/// If a column and `output` have different lengths.
+pub fn eval_columns(
+    expr: &Expr,
+    columns: &[(&str, &[Number])],
+    output: &mut [Number],
+) -> EvalResult<()> {
+    let slots = columns.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let values = columns
+        .iter()
+        .map(|(_, column)| *column)
+        .collect::<Vec<_>>();
+
+    Bytecode::compile(expr, &slots)?.eval_columns(&values, output)
+}
+
+impl Bytecode<Number> {
+    /// Evaluate the bytecode with the values of the slots taken from each row of
+    /// `columns`, into the same row of `output`.
+    ///
+    /// # Panics
+    /// If a column and `output` have different lengths.
+    pub fn eval_columns(&self, columns: &[&[Number]], output: &mut [Number]) -> EvalResult<()> {
+        if let Some(missing) = self.slots().get(columns.len()) {
+            return Err(EvalError::UnboundVariable(missing.clone()));
+        }
+        for column in columns {
+            assert_eq!(column.len(), output.len(), "Columns of different lengths");
+        }
+
+        let mut stack = vec![[0.; CHUNK]; self.stack_size()];
+        for (index, chunk) in output.chunks_mut(CHUNK).enumerate() {
+            let rows = index * CHUNK..index * CHUNK + chunk.len();
+            let len = chunk.len();
+            let mut top = 0;
+
+            for instruction in self.instructions() {
+                match instruction {
+                    Instruction::Push(num) => {
+                        stack[top][..len].fill(*num);
+                        top += 1;
+                    }
+                    Instruction::Load(slot) => {
+                        stack[top][..len].copy_from_slice(&columns[*slot][rows.clone()]);
+                        top += 1;
+                    }
+                    Instruction::Negate => {
+                        for x in &mut stack[top - 1][..len] {
+                            *x = -*x;
+                        }
+                    }
+                    Instruction::Factorial => {
+                        for x in &mut stack[top - 1][..len] {
+                            *x = factorial(*x, false).unwrap_or(Number::NAN);
+                        }
+                    }
+                    Instruction::BinOp(op) => {
+                        top -= 1;
+                        let (lower, upper) = stack.split_at_mut(top);
+                        binary(*op, &mut lower[top - 1][..len], &upper[0][..len]);
+                    }
+                    Instruction::Call(builtin, count) => {
+                        top -= count;
+                        for row in 0..len {
+                            let mut args = [0.; 4];
+                            let result = if *count <= args.len() {
+                                for (arg, values) in args.iter_mut().zip(&stack[top..]) {
+                                    *arg = values[row];
+                                }
+                                (builtin.function)(&args[..*count])
+                            } else {
+                                let args = stack[top..top + count]
+                                    .iter()
+                                    .map(|values| values[row])
+                                    .collect::<Vec<_>>();
+                                (builtin.function)(&args)
+                            };
+                            stack[top][row] = result;
+                        }
+                        top += 1;
+                    }
+                    Instruction::Branch(_) | Instruction::Jump(_) => {}
+                    Instruction::Select => {
+                        top -= 2;
+                        let (lower, upper) = stack.split_at_mut(top);
+                        let rows = lower[top - 1][..len]
+                            .iter_mut()
+                            .zip(&upper[0][..len])
+                            .zip(&upper[1][..len]);
+                        for ((condition, then), otherwise) in rows {
+                            *condition = if *condition != 0. { *then } else { *otherwise };
+                        }
+                    }
+                }
+            }
+
+            chunk.copy_from_slice(&stack[0][..len]);
+        }
+
+        Ok(())
+    }
+}
+
+/// Apply `op` to each row, storing the result in `left`.
+fn binary(op: BinOpType, left: &mut [Number], right: &[Number]) {
+    // One loop per operator so that each of them can be vectorised
+    let rows = left.iter_mut().zip(right);
+    match op {
+        BinOpType::Add => rows.for_each(|(x, y)| *x += y),
+        BinOpType::Sub => rows.for_each(|(x, y)| *x -= y),
+        BinOpType::Mul => rows.for_each(|(x, y)| *x *= y),
+        BinOpType::Div => rows.for_each(|(x, y)| *x /= y),
+        BinOpType::Mod => rows.for_each(|(x, y)| *x -= y * (*x / y).floor()),
+        BinOpType::FloorDiv => rows.for_each(|(x, y)| *x = (*x / y).floor()),
+        BinOpType::Pow => rows.for_each(|(x, y)| *x = x.powf(*y)),
+        BinOpType::Eq => rows.for_each(|(x, y)| *x = boolean(*x == *y)),
+        BinOpType::Ne => rows.for_each(|(x, y)| *x = boolean(*x != *y)),
+        BinOpType::Lt => rows.for_each(|(x, y)| *x = boolean(*x < *y)),
+        BinOpType::Le => rows.for_each(|(x, y)| *x = boolean(*x <= *y)),
+        BinOpType::Gt => rows.for_each(|(x, y)| *x = boolean(*x > *y)),
+        BinOpType::Ge => rows.for_each(|(x, y)| *x = boolean(*x >= *y)),
+        BinOpType::And => rows.for_each(|(x, y)| *x = boolean(*x != 0. && *y != 0.)),
+        BinOpType::Or => rows.for_each(|(x, y)| *x = boolean(*x != 0. || *y != 0.)),
+    }
+}
+
+fn boolean(condition: bool) -> Number {
+    if condition {
+        1.
+    } else {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::Bytecode;
+    use crate::columns::eval_columns;
+    use crate::errors::EvalError;
+    use crate::parser::Parser;
+    use crate::token::tokenize;
+
+    #[test]
+    fn same_as_bytecode() {
+        let sources = [
+            "2x + y",
+            "-x ** y / 3",
+            "sin(x) * cos(y) + atan2(y, x)",
+            "log(x ** 2 + 1, 3) - min(x, y, 0, 1, -1)",
+            "1 / (x - 1)",
+            "sqrt(y) + pi",
+            "x > 0 ? sqrt(x) : y < 0 and 1 / y",
+            "not x or ln(x) >= y != (x == y)",
+            "x % y + x // y - (x + 10%)",
+            "x! + y!",
+        ];
+        // Not a multiple of the chunk size
+        let xs = (0..1000).map(|i| i as f64 / 100. - 3.).collect::<Vec<_>>();
+        let ys = (0..1000).map(|i| (i % 17) as f64 - 8.).collect::<Vec<_>>();
+
+        for source in sources.iter() {
+            let expr = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+            let bytecode = Bytecode::compile(&expr, &["x", "y"]).unwrap();
+
+            let mut output = vec![0.; xs.len()];
+            bytecode.eval_columns(&[&xs, &ys], &mut output).unwrap();
+
+            for (row, result) in output.iter().enumerate() {
+                let expected = bytecode.eval(&[xs[row], ys[row]]);
+                assert!(
+                    *result == expected || (result.is_nan() && expected.is_nan()),
+                    "{} at row {}",
+                    source,
+                    row
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn missing_column() {
+        let expr = Parser::new(tokenize("x + y").unwrap()).parse().unwrap();
+        let mut output = [0.; 2];
+
+        assert_eq!(
+            eval_columns(&expr, &[("x", &[1., 2.])], &mut output),
+            Err(EvalError::UnboundVariable("y".into()))
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! # Complex module
+//! Contains the [Complex] number type, able to represent the imaginary unit `i`.
+//!
+//! Powers of negative numbers and the builtin functions outside of their real domain,
+//! like `sqrt(-4)` or `asin(2)`, return their principal complex value. Functions of the
+//! real numbers only, like `floor` or `max`, reject non-real arguments.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::complex::Complex;
+//! # use rust_calculator::eval::Eval;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize_as;
+//! let expr = Parser::new(tokenize_as::<Complex>("sqrt(-4) + 1").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! assert_eq!(expr.try_eval().unwrap().to_string(), "1 + 2i");
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num_complex::Complex64;
+
+use crate::eval::builtin;
+use crate::numeric::Numeric;
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct Complex(pub Complex64);
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self(Complex64::new(re, im))
+    }
+
+    pub fn is_real(&self) -> bool {
+        self.0.im == 0.
+    }
+}
+
+impl Display for Complex {
+    /// Formatted as `a + bi`, without the parts that are zero.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Complex64 { re, im } = self.0;
+
+        if im == 0. {
+            write!(f, "{}", re)
+        } else if re == 0. {
+            write!(f, "{}i", im)
+        } else if im.is_sign_negative() {
+            write!(f, "{} - {}i", re, -im)
+        } else {
+            write!(f, "{} + {}i", re, im)
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self(self.0 * other.0)
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        Self(self.0 / other.0)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        // Keep the zero imaginary part positive, `sqrt(-4)` is above the branch cut
+        Self::new(-self.0.re, 0. - self.0.im)
+    }
+}
+
+impl Numeric for Complex {
+    fn from_literal(literal: &str) -> Self {
+        Self::from_f64(f64::from_literal(literal))
+    }
+
+    fn from_f64(value: f64) -> Self {
+        Self::new(value, 0.)
+    }
+
+    /// NaN unless the number is real.
+    fn to_f64(&self) -> f64 {
+        if self.is_real() {
+            self.0.re
+        } else {
+            f64::NAN
+        }
+    }
+
+    fn e() -> Self {
+        Self::from_f64(std::f64::consts::E)
+    }
+
+    fn pi() -> Self {
+        Self::from_f64(std::f64::consts::PI)
+    }
+
+    fn i() -> Option<Self> {
+        Some(Self::new(0., 1.))
+    }
+
+    /// Principal value of the power.
+    fn pow(&self, exponent: &Self) -> Self {
+        if exponent.is_integer() && exponent.0.re.abs() <= i32::MAX as f64 {
+            // Exact for small integers, `(-2) ** 2` is 4 and not 4 + 0.0000000000000005i
+            Self(self.0.powi(exponent.0.re as i32))
+        } else if self.is_zero() {
+            match (exponent.0.re, exponent.is_real()) {
+                (re, _) if re > 0. => Self::default(),
+                (re, true) if re < 0. => Self::from_f64(f64::INFINITY),
+                _ => Self::from_f64(f64::NAN),
+            }
+        } else {
+            Self(self.0.powc(exponent.0))
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.re == 0. && self.0.im == 0.
+    }
+
+    /// Whether the number is a negative real.
+    fn is_negative(&self) -> bool {
+        self.is_real() && self.0.re < 0.
+    }
+
+    fn is_integer(&self) -> bool {
+        self.is_real() && self.0.re.fract() == 0.
+    }
+
+    fn is_finite(&self) -> bool {
+        self.0.is_finite()
+    }
+
+    fn is_nan(&self) -> bool {
+        self.0.is_nan()
+    }
+
+    /// Real arguments in the domain of the function are computed on [f64], the others
+    /// with the complex extension of the function if there is one.
+    fn apply(name: &str, args: &[Self]) -> Option<Self> {
+        let floats = args.iter().map(Self::to_f64).collect::<Vec<_>>();
+        if args.iter().all(Self::is_real) && builtin(name).is_none_or(|b| (b.domain)(&floats)) {
+            return None;
+        }
+
+        let result = match (name, args) {
+            ("sqrt", [x]) => x.0.sqrt(),
+            ("exp", [x]) => x.0.exp(),
+            ("ln", [x]) => x.0.ln(),
+            ("log2", [x]) => x.0.log2(),
+            ("log10", [x]) | ("log", [x]) => x.0.log10(),
+            ("log", [x, base]) => x.0.ln() / base.0.ln(),
+            ("sin", [x]) => x.0.sin(),
+            ("cos", [x]) => x.0.cos(),
+            ("tan", [x]) => x.0.tan(),
+            ("asin", [x]) => x.0.asin(),
+            ("acos", [x]) => x.0.acos(),
+            ("atan", [x]) => x.0.atan(),
+            ("sinh", [x]) => x.0.sinh(),
+            ("cosh", [x]) => x.0.cosh(),
+            ("tanh", [x]) => x.0.tanh(),
+            ("asinh", [x]) => x.0.asinh(),
+            ("acosh", [x]) => x.0.acosh(),
+            ("atanh", [x]) => x.0.atanh(),
+            ("abs", [x]) => x.0.norm().into(),
+            _ if args.iter().all(Self::is_real) => return None,
+            _ => return Some(Self::from_f64(f64::NAN)),
+        };
+
+        // Poles like `ln(0)` are outside of the domain as with real numbers
+        Some(if result.is_finite() {
+            Self(result)
+        } else {
+            Self::from_f64(f64::NAN)
+        })
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(value: f64) -> Self {
+        Self::from_f64(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::complex::Complex;
+    use crate::errors::EvalError;
+    use crate::eval::Eval;
+    use crate::parser::Parser;
+    use crate::token::tokenize_as;
+
+    fn eval(source: &str) -> Result<Complex, EvalError> {
+        Parser::new(tokenize_as::<Complex>(source).unwrap())
+            .parse()
+            .unwrap()
+            .try_eval()
+    }
+
+    fn assert_close(source: &str, re: f64, im: f64) {
+        let result = eval(source).unwrap();
+        assert!(
+            (result.0.re - re).abs() < 1e-12 && (result.0.im - im).abs() < 1e-12,
+            "{} = {}, expected {}",
+            source,
+            result,
+            Complex::new(re, im)
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Complex::new(1.5, 0.).to_string(), "1.5");
+        assert_eq!(Complex::new(0., -2.).to_string(), "-2i");
+        assert_eq!(Complex::new(1., 2.).to_string(), "1 + 2i");
+        assert_eq!(Complex::new(-1., -0.5).to_string(), "-1 - 0.5i");
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("i * i"), Ok(Complex::new(-1., 0.)));
+        assert_eq!(eval("(1 + 2i)(3 - i)"), Ok(Complex::new(5., 5.)));
+        assert_eq!(eval("1 / i"), Ok(Complex::new(0., -1.)));
+        assert_eq!(eval("i ** 2"), Ok(Complex::new(-1., 0.)));
+        assert_eq!(eval("(-2) ** 3"), Ok(Complex::new(-8., 0.)));
+        assert_close("(-1) ** 0.5", 0., 1.);
+        assert_close("(-8) ** (1/3)", 1., 3f64.sqrt());
+        assert_close("i ** i", (-std::f64::consts::FRAC_PI_2).exp(), 0.);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("sqrt(-4)"), Ok(Complex::new(0., 2.)));
+        assert_eq!(eval("sqrt(4)"), Ok(Complex::new(2., 0.)));
+        assert_eq!(eval("abs(3 + 4i)"), Ok(Complex::new(5., 0.)));
+        assert_close("exp(i pi)", -1., 0.);
+        assert_close("ln(-1)", 0., std::f64::consts::PI);
+        assert_close("asin(2)", std::f64::consts::FRAC_PI_2, -1.3169578969248166);
+        assert_close("log(-100)", 2., std::f64::consts::PI / 10f64.ln());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1 / (i - i)"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("0 ** -1"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("floor(i)"), Err(EvalError::Domain("floor".into())));
+        assert_eq!(eval("max(1, i)"), Err(EvalError::Domain("max".into())));
+        assert_eq!(eval("ln(0)"), Err(EvalError::Domain("ln".into())));
+    }
+}
@@ -0,0 +1,1004 @@
+//! # Decimal module
+//! Contains the [Decimal] number type, an arbitrary-precision decimal floating point
+//! whose operations are rounded according to a [DecimalContext].
+//!
+//! Like in a decimal spreadsheet, literals are read exactly and every operation is
+//! rounded to the precision of the context. The operators, the constants `e` and `pi`
+//! and the functions `sqrt`, `exp`, `ln`, `log`, `log2`, `log10`, `abs`, `sign`,
+//! `min`, `max` and the rounding functions are computed at the requested precision,
+//! the other functions are computed on [f64].
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::decimal::{Decimal, DecimalContext, Rounding};
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize_as;
+//! let expr = Parser::new(tokenize_as::<Decimal>("2/3").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! let context = DecimalContext::new(5, Rounding::HalfEven);
+//! assert_eq!(context.eval(&expr).unwrap().to_string(), "0.66667");
+//!
+//! let context = DecimalContext::new(5, Rounding::Down);
+//! assert_eq!(context.eval(&expr).unwrap().to_string(), "0.66666");
+//! # }
+//! ```
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Pow, Signed, Zero};
+
+use crate::ast::Expr;
+use crate::errors::EvalResult;
+use crate::eval::{Environment, Eval};
+use crate::numeric::{decimal_parts, Numeric};
+
+/// Extra digits used by the intermediate steps of the transcendental functions.
+const GUARD_DIGITS: usize = 10;
+
+/// Largest exponent of a literal, far beyond any useful value but safe from overflows.
+const MAX_EXPONENT: u64 = i32::MAX as u64;
+
+/// How to round a number that can't be represented with the precision of the context.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Rounding {
+    /// To the nearest, ties to the even neighbour.
+    HalfEven,
+    /// To the nearest, ties away from zero.
+    HalfUp,
+    /// To the nearest, ties toward zero.
+    HalfDown,
+    /// Away from zero.
+    Up,
+    /// Toward zero.
+    Down,
+    /// Toward positive infinity.
+    Ceiling,
+    /// Toward negative infinity.
+    Floor,
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "half-even" => Self::HalfEven,
+            "half-up" => Self::HalfUp,
+            "half-down" => Self::HalfDown,
+            "up" => Self::Up,
+            "down" => Self::Down,
+            "ceiling" => Self::Ceiling,
+            "floor" => Self::Floor,
+            _ => return Err(format!("Unknown rounding mode '{}'", s)),
+        })
+    }
+}
+
+/// Precision and rounding mode of the [Decimal] operations.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct DecimalContext {
+    /// Number of significant digits, at least 1.
+    pub precision: usize,
+    pub rounding: Rounding,
+}
+
+impl Default for DecimalContext {
+    /// 34 digits rounded half-even, like the IEEE 754 decimal128 format.
+    fn default() -> Self {
+        Self::new(34, Rounding::HalfEven)
+    }
+}
+
+thread_local! {
+    static CONTEXT: Cell<DecimalContext> = Cell::new(DecimalContext::default());
+}
+
+impl DecimalContext {
+    pub fn new(precision: usize, rounding: Rounding) -> Self {
+        Self {
+            precision: precision.max(1),
+            rounding,
+        }
+    }
+
+    /// Context of the [Decimal] operations of the current thread.
+    pub fn current() -> Self {
+        CONTEXT.with(Cell::get)
+    }
+
+    /// Run `f` with `self` as the context of the [Decimal] operations of the current thread.
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        struct Restore(DecimalContext);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CONTEXT.with(|context| context.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CONTEXT.with(|context| context.replace(*self)));
+        f()
+    }
+
+    /// Evaluate `expr` with every operation rounded according to `self`.
+    pub fn eval(&self, expr: &Expr<Decimal>) -> EvalResult<Decimal> {
+        self.eval_in(expr, &Environment::new())
+    }
+
+    /// Evaluate `expr` with the variables bound in `env` and every operation rounded
+    /// according to `self`.
+    pub fn eval_in(&self, expr: &Expr<Decimal>, env: &Environment<Decimal>) -> EvalResult<Decimal> {
+        self.scope(|| expr.eval_in(env).map(|result| result.round(self)))
+    }
+
+    /// Same rounding mode with `extra` more digits, for the intermediate steps.
+    fn guarded(&self, extra: usize) -> Self {
+        Self::new(self.precision + extra, self.rounding)
+    }
+}
+
+/// An arbitrary-precision decimal number, `mantissa * 10^exponent`, or NaN.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Decimal {
+    /// Always normalized, the mantissa has no trailing zeros and zero has a zero exponent.
+    Finite { mantissa: BigInt, exponent: i64 },
+    /// Result of the operations without a meaningful value, like `0/0` or `ln(-1)`.
+    NaN,
+}
+
+impl Decimal {
+    pub fn new(mantissa: impl Into<BigInt>, exponent: i64) -> Self {
+        let mut mantissa = mantissa.into();
+        let mut exponent = exponent;
+
+        if mantissa.is_zero() {
+            exponent = 0;
+        } else {
+            let ten = BigInt::from(10);
+            loop {
+                let (quotient, remainder) = mantissa.div_rem(&ten);
+                if !remainder.is_zero() {
+                    break;
+                }
+                mantissa = quotient;
+                exponent += 1;
+            }
+        }
+
+        Self::Finite { mantissa, exponent }
+    }
+
+    /// `mantissa * 10^exponent`, NaN if computing the exponent overflowed or if it is
+    /// beyond [MAX_EXPONENT].
+    fn bounded(mantissa: BigInt, exponent: Option<i64>) -> Self {
+        match exponent {
+            _ if mantissa.is_zero() => Self::zero(),
+            Some(exponent) if exponent.unsigned_abs() <= MAX_EXPONENT => {
+                Self::new(mantissa, exponent)
+            }
+            _ => Self::NaN,
+        }
+    }
+
+    fn zero() -> Self {
+        Self::new(0, 0)
+    }
+
+    fn one() -> Self {
+        Self::new(1, 0)
+    }
+
+    /// Power of ten of the most significant digit.
+    fn adjusted(mantissa: &BigInt, exponent: i64) -> i64 {
+        digits(mantissa) - 1 + exponent
+    }
+
+    /// Round to the precision of `context`.
+    pub fn round(&self, context: &DecimalContext) -> Self {
+        match self {
+            Self::Finite { mantissa, exponent } => {
+                let excess = digits(mantissa) - context.precision as i64;
+                if excess > 0 {
+                    round_at(mantissa, *exponent, exponent + excess, context.rounding)
+                } else {
+                    self.clone()
+                }
+            }
+            Self::NaN => Self::NaN,
+        }
+    }
+
+    /// Round to an integer.
+    fn round_integer(&self, rounding: Rounding) -> Self {
+        match self {
+            Self::Finite { mantissa, exponent } if *exponent < 0 => {
+                round_at(mantissa, *exponent, 0, rounding)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    fn sign(&self) -> Ordering {
+        match self {
+            Self::Finite { mantissa, .. } => mantissa.sign().cmp(&num_bigint::Sign::NoSign),
+            Self::NaN => Ordering::Equal,
+        }
+    }
+
+    fn abs(&self) -> Self {
+        match self {
+            Self::Finite { mantissa, exponent } => Self::Finite {
+                mantissa: mantissa.abs(),
+                exponent: *exponent,
+            },
+            Self::NaN => Self::NaN,
+        }
+    }
+
+    /// `None` if one of them is NaN.
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::NaN, _) | (_, Self::NaN) => None,
+            _ => Some(add_exact(self, &-other.clone()).sign()),
+        }
+    }
+
+    /// Sum rounded to `context`, without computing digits far below the precision.
+    fn add_rounded(&self, other: &Self, context: &DecimalContext) -> Self {
+        match (self, other) {
+            (
+                Self::Finite {
+                    mantissa: left,
+                    exponent: left_exponent,
+                },
+                Self::Finite {
+                    mantissa: right,
+                    exponent: right_exponent,
+                },
+            ) if !left.is_zero() && !right.is_zero() => {
+                let left_adjusted = Self::adjusted(left, *left_exponent);
+                let right_adjusted = Self::adjusted(right, *right_exponent);
+                let (big, big_adjusted, big_exponent, small, small_adjusted) =
+                    if left_adjusted >= right_adjusted {
+                        (self, left_adjusted, *left_exponent, right, right_adjusted)
+                    } else {
+                        (other, right_adjusted, *right_exponent, left, left_adjusted)
+                    };
+
+                // Below this position the small operand only matters by its sign
+                let sticky = (big_adjusted - context.precision as i64 - 2).min(big_exponent - 1);
+
+                if small_adjusted < sticky {
+                    let tiny = Self::new(small.signum(), sticky - 1);
+                    add_exact(big, &tiny).round(context)
+                } else {
+                    add_exact(self, other).round(context)
+                }
+            }
+            _ => add_exact(self, other).round(context),
+        }
+    }
+
+    fn mul_rounded(&self, other: &Self, context: &DecimalContext) -> Self {
+        match (self, other) {
+            (
+                Self::Finite {
+                    mantissa: left,
+                    exponent: left_exponent,
+                },
+                Self::Finite {
+                    mantissa: right,
+                    exponent: right_exponent,
+                },
+            ) => Self::bounded(left * right, left_exponent.checked_add(*right_exponent))
+                .round(context),
+            _ => Self::NaN,
+        }
+    }
+
+    fn div_rounded(&self, other: &Self, context: &DecimalContext) -> Self {
+        match (self, other) {
+            (
+                Self::Finite {
+                    mantissa: left,
+                    exponent: left_exponent,
+                },
+                Self::Finite {
+                    mantissa: right,
+                    exponent: right_exponent,
+                },
+            ) if !right.is_zero() => {
+                if left.is_zero() {
+                    return Self::zero();
+                }
+
+                // Compute at least 2 more digits than needed, plus a sticky one if inexact
+                let shift = (context.precision as i64 + 2 + digits(right) - digits(left)).max(0);
+                let numerator = left.abs() * pow10(shift);
+                let (quotient, remainder) = numerator.div_rem(&right.abs());
+                let mut mantissa: BigInt = quotient * 10;
+                if !remainder.is_zero() {
+                    mantissa += 1;
+                }
+                if left.is_negative() != right.is_negative() {
+                    mantissa = -mantissa;
+                }
+
+                let exponent = left_exponent
+                    .checked_sub(*right_exponent)
+                    .and_then(|exponent| exponent.checked_sub(shift + 1));
+                match Self::bounded(mantissa, exponent) {
+                    Self::Finite { mantissa, exponent } => {
+                        let excess = digits(&mantissa) - context.precision as i64;
+                        round_at(&mantissa, exponent, exponent + excess, context.rounding)
+                    }
+                    Self::NaN => Self::NaN,
+                }
+            }
+            _ => Self::NaN,
+        }
+    }
+
+    /// Square root, NaN for negative numbers.
+    fn sqrt(&self, context: &DecimalContext) -> Self {
+        match self {
+            Self::Finite { mantissa, exponent } if !mantissa.is_negative() => {
+                if mantissa.is_zero() {
+                    return Self::zero();
+                }
+
+                // Integer square root of a radicand with at least 2 more digits than
+                // needed and an even exponent, plus a sticky digit if inexact
+                let target = 2 * (context.precision as i64 + 2);
+                let mut shift = (target - digits(mantissa)).max(0);
+                if (exponent - shift) % 2 != 0 {
+                    shift += 1;
+                }
+                let radicand = mantissa * pow10(shift);
+                let root = radicand.sqrt();
+                let mut root_mantissa = &root * 10;
+                if &root * &root != radicand {
+                    root_mantissa += 1;
+                }
+
+                let root_exponent = (exponent - shift) / 2 - 1;
+                let excess = digits(&root_mantissa) - context.precision as i64;
+                round_at(
+                    &root_mantissa,
+                    root_exponent,
+                    root_exponent + excess,
+                    context.rounding,
+                )
+            }
+            _ => Self::NaN,
+        }
+    }
+
+    /// Exponential function, NaN if the result is too large to be represented.
+    fn exp(&self, context: &DecimalContext) -> Self {
+        let float = self.to_f64();
+        if self.is_nan() || float > 1e15 {
+            return Self::NaN;
+        } else if float < -1e15 {
+            return Self::zero();
+        } else if self.is_zero() {
+            return Self::one();
+        }
+
+        // Reduce the argument below 0.1 so the series converges quickly, the result is
+        // squared back which loses about 0.3 digits each time
+        let halvings = (float.abs() * 10.).log2().ceil().max(0.) as u32;
+        let working = context.guarded(GUARD_DIGITS + halvings as usize / 3);
+        let two = Self::new(2, 0);
+        let mut reduced = self.clone();
+        for _ in 0..halvings {
+            reduced = reduced.div_rounded(&two, &working);
+        }
+
+        let mut sum = Self::one();
+        let mut term = Self::one();
+        for k in 1.. {
+            term = term
+                .mul_rounded(&reduced, &working)
+                .div_rounded(&Self::new(k, 0), &working);
+            let next = sum.add_rounded(&term, &working);
+            if next == sum {
+                break;
+            }
+            sum = next;
+        }
+
+        for _ in 0..halvings {
+            sum = sum.mul_rounded(&sum, &working);
+        }
+
+        sum.round(context)
+    }
+
+    /// Natural logarithm, NaN for non-positive numbers.
+    fn ln(&self, context: &DecimalContext) -> Self {
+        match self {
+            Self::Finite { mantissa, exponent } if mantissa.is_positive() => {
+                // ln(x) = ln(x / 10^a) + a * ln(10), with x / 10^a in [1, 10)
+                let adjusted = Self::adjusted(mantissa, *exponent);
+                let working = context.guarded(GUARD_DIGITS + digits(&adjusted.into()) as usize);
+                let reduced = Self::new(mantissa.clone(), exponent - adjusted);
+
+                let mut result = reduced.ln_reduced(&working);
+                if adjusted != 0 {
+                    let ln10 = Self::new(10, 0).ln_reduced(&working);
+                    let shift = Self::new(adjusted, 0).mul_rounded(&ln10, &working);
+                    result = result.add_rounded(&shift, &working);
+                }
+
+                result.round(context)
+            }
+            _ => Self::NaN,
+        }
+    }
+
+    /// Natural logarithm of a number close to 1, using Halley's method on `exp`.
+    fn ln_reduced(&self, context: &DecimalContext) -> Self {
+        // Results close to zero need as many more digits as leading zeros
+        let distance = add_exact(self, &-Self::one());
+        let extra = match &distance {
+            Self::Finite { mantissa, exponent } if !mantissa.is_zero() => {
+                (-Self::adjusted(mantissa, *exponent)).max(0) as usize
+            }
+            _ => return Self::zero(),
+        };
+        let working = context.guarded(GUARD_DIGITS + extra);
+
+        let two = Self::new(2, 0);
+        let mut guess = Self::from_f64(self.to_f64().ln());
+        loop {
+            let exp = guess.exp(&working);
+            let step = add_exact(self, &-exp.clone())
+                .mul_rounded(&two, &working)
+                .div_rounded(&self.add_rounded(&exp, &working), &working);
+            let next = guess.add_rounded(&step, &working);
+            if next == guess || step.is_zero() {
+                break;
+            }
+            guess = next;
+        }
+
+        guess.round(context)
+    }
+
+    /// Power with an integer exponent, by squaring.
+    fn powi(&self, exponent: &BigInt, context: &DecimalContext) -> Self {
+        if exponent.is_negative() {
+            if self.is_zero() {
+                return Self::NaN;
+            }
+            let working = context.guarded(GUARD_DIGITS);
+            return Self::one()
+                .div_rounded(&self.powi(&-exponent, &working), &working)
+                .round(context);
+        }
+
+        let working = context.guarded(GUARD_DIGITS + digits(exponent) as usize);
+        let mut result = Self::one();
+        let mut base = self.clone();
+        let mut exponent = exponent.clone();
+        let two = BigInt::from(2);
+        while !exponent.is_zero() {
+            let (half, bit) = exponent.div_rem(&two);
+            if !bit.is_zero() {
+                result = result.mul_rounded(&base, &working);
+            }
+            exponent = half;
+            if !exponent.is_zero() {
+                base = base.mul_rounded(&base, &working);
+            }
+        }
+
+        result.round(context)
+    }
+
+    fn pow_rounded(&self, exponent: &Self, context: &DecimalContext) -> Self {
+        match exponent {
+            // From 10^19 on, the result overflows or underflows unless the base is 0 or
+            // ±1, so the exponent isn't expanded to a huge integer
+            Self::Finite {
+                mantissa,
+                exponent: exponent_exponent,
+            } if *exponent_exponent >= 0 && Self::adjusted(mantissa, *exponent_exponent) > 18 => {
+                let odd = *exponent_exponent == 0 && mantissa.is_odd();
+                let magnitude = self.abs().pow_real(exponent, context);
+                if odd && self.is_negative() {
+                    -magnitude
+                } else {
+                    magnitude
+                }
+            }
+            Self::Finite {
+                mantissa,
+                exponent: exponent_exponent,
+            } if *exponent_exponent >= 0 => {
+                let integer = mantissa * pow10(*exponent_exponent);
+                self.powi(&integer, context)
+            }
+            Self::Finite { .. } => self.pow_real(exponent, context),
+            Self::NaN => Self::NaN,
+        }
+    }
+
+    /// Power with a real exponent, NaN for negative bases.
+    fn pow_real(&self, exponent: &Self, context: &DecimalContext) -> Self {
+        match self.sign() {
+            _ if self.is_nan() => Self::NaN,
+            Ordering::Less => Self::NaN,
+            Ordering::Equal if exponent.is_negative() => Self::NaN,
+            Ordering::Equal => Self::zero(),
+            Ordering::Greater => {
+                // x ** y = exp(y * ln(x)), the result is scaled by the logarithm
+                let working = context.guarded(GUARD_DIGITS + 10);
+                let ln = self.ln(&working);
+                exponent.mul_rounded(&ln, &working).exp(context)
+            }
+        }
+    }
+
+    /// `pi` using Machin's formula, `pi = 16 atan(1/5) - 4 atan(1/239)`.
+    fn compute_pi(context: &DecimalContext) -> Self {
+        let digits = context.precision as i64 + GUARD_DIGITS as i64;
+        let scale = pow10(digits);
+
+        // atan(1/n) scaled by 10^digits
+        let atan_inverse = |n: u32| {
+            let n_squared = BigInt::from(n * n);
+            let mut power = &scale / n;
+            let mut sum = power.clone();
+            for k in 1u32.. {
+                power /= &n_squared;
+                if power.is_zero() {
+                    break;
+                }
+                let term = &power / (2 * k + 1);
+                if k % 2 == 0 {
+                    sum += term;
+                } else {
+                    sum -= term;
+                }
+            }
+            sum
+        };
+
+        let pi = atan_inverse(5) * 16 - atan_inverse(239) * 4;
+        Self::new(pi, -digits).round(context)
+    }
+
+    /// `e` as the sum of the inverses of the factorials.
+    fn compute_e(context: &DecimalContext) -> Self {
+        let digits = context.precision as i64 + GUARD_DIGITS as i64;
+        let mut term = pow10(digits);
+        let mut sum = BigInt::zero();
+        for k in 1u32.. {
+            if term.is_zero() {
+                break;
+            }
+            sum += &term;
+            term /= k;
+        }
+
+        Self::new(sum, -digits).round(context)
+    }
+}
+
+/// Number of decimal digits of the mantissa, 1 for zero.
+fn digits(mantissa: &BigInt) -> i64 {
+    if mantissa.is_zero() {
+        1
+    } else {
+        mantissa.magnitude().to_string().len() as i64
+    }
+}
+
+fn pow10(exponent: i64) -> BigInt {
+    BigInt::from(10).pow(exponent as u64)
+}
+
+/// Exact sum, NaN if any operand is NaN.
+fn add_exact(left: &Decimal, right: &Decimal) -> Decimal {
+    match (left, right) {
+        (
+            Decimal::Finite {
+                mantissa: left,
+                exponent: left_exponent,
+            },
+            Decimal::Finite {
+                mantissa: right,
+                exponent: right_exponent,
+            },
+        ) => {
+            let exponent = *left_exponent.min(right_exponent);
+            Decimal::new(
+                left * pow10(left_exponent - exponent) + right * pow10(right_exponent - exponent),
+                exponent,
+            )
+        }
+        _ => Decimal::NaN,
+    }
+}
+
+/// Drop the digits of `mantissa * 10^exponent` below `10^new_exponent`,
+/// rounding the remaining ones with `rounding`.
+fn round_at(mantissa: &BigInt, exponent: i64, new_exponent: i64, rounding: Rounding) -> Decimal {
+    if new_exponent <= exponent {
+        return Decimal::new(mantissa.clone(), exponent);
+    }
+
+    let divisor = pow10(new_exponent - exponent);
+    let (quotient, remainder) = mantissa.div_rem(&divisor);
+    let negative = mantissa.is_negative();
+
+    let increment = !remainder.is_zero() && {
+        let half = (remainder.abs() * 2u32).cmp(&divisor);
+        match rounding {
+            Rounding::HalfEven => {
+                half == Ordering::Greater || (half == Ordering::Equal && quotient.is_odd())
+            }
+            Rounding::HalfUp => half != Ordering::Less,
+            Rounding::HalfDown => half == Ordering::Greater,
+            Rounding::Up => true,
+            Rounding::Down => false,
+            Rounding::Ceiling => !negative,
+            Rounding::Floor => negative,
+        }
+    };
+
+    let quotient = match (increment, negative) {
+        (false, _) => quotient,
+        (true, false) => quotient + 1,
+        (true, true) => quotient - 1,
+    };
+    Decimal::new(quotient, new_exponent)
+}
+
+impl Display for Decimal {
+    /// Plain notation unless the number is very large or very small.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (mantissa, exponent) = match self {
+            Self::Finite { mantissa, exponent } => (mantissa, *exponent),
+            Self::NaN => return write!(f, "NaN"),
+        };
+
+        let sign = if mantissa.is_negative() { "-" } else { "" };
+        let digits = mantissa.magnitude().to_string();
+        let adjusted = Self::adjusted(mantissa, exponent);
+
+        if exponent == 0 || (exponent > 0 && adjusted < 21) {
+            write!(f, "{}{}{}", sign, digits, "0".repeat(exponent as usize))
+        } else if exponent < 0 && adjusted >= -6 {
+            let point = digits.len() as i64 + exponent;
+            if point > 0 {
+                let (integer, decimals) = digits.split_at(point as usize);
+                write!(f, "{}{}.{}", sign, integer, decimals)
+            } else {
+                write!(f, "{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+            }
+        } else {
+            let (first, rest) = digits.split_at(1);
+            let point = if rest.is_empty() { "" } else { "." };
+            write!(f, "{}{}{}{}e{}", sign, first, point, rest, adjusted)
+        }
+    }
+}
+
+impl Add for Decimal {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.add_rounded(&other, &DecimalContext::current())
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.add_rounded(&-other, &DecimalContext::current())
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.mul_rounded(&other, &DecimalContext::current())
+    }
+}
+
+impl Div for Decimal {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.div_rounded(&other, &DecimalContext::current())
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        match self {
+            Self::Finite { mantissa, exponent } => Self::Finite {
+                mantissa: -mantissa,
+                exponent,
+            },
+            Self::NaN => Self::NaN,
+        }
+    }
+}
+
+/// The operations are rounded according to [DecimalContext::current].
+impl Numeric for Decimal {
+    /// NaN if the exponent is too large to be represented.
+    fn from_literal(literal: &str) -> Self {
+        let (digits, power) = decimal_parts(literal);
+
+        // The tokenizer only produces valid literals
+        let mantissa = digits.parse::<BigInt>().unwrap();
+        if mantissa.is_zero() {
+            Self::new(mantissa, 0)
+        } else if power.unsigned_abs() > MAX_EXPONENT {
+            Self::NaN
+        } else {
+            Self::new(mantissa, power)
+        }
+    }
+
+    /// Shortest decimal representation of `value`.
+    fn from_f64(value: f64) -> Self {
+        if !value.is_finite() {
+            return Self::NaN;
+        }
+
+        // Formatted as `<mantissa>e<exponent>` with an optional dot in the mantissa
+        let formatted = format!("{:e}", value);
+        let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+        let exponent = exponent[1..].parse::<i64>().unwrap();
+        let decimals = mantissa.find('.').map_or(0, |dot| mantissa.len() - dot - 1);
+        let mantissa = mantissa.replace('.', "").parse::<BigInt>().unwrap();
+
+        Self::new(mantissa, exponent - decimals as i64)
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Self::Finite { mantissa, exponent } => format!("{}e{}", mantissa, exponent)
+                .parse()
+                .unwrap_or(f64::NAN),
+            Self::NaN => f64::NAN,
+        }
+    }
+
+    fn e() -> Self {
+        Self::compute_e(&DecimalContext::current())
+    }
+
+    fn pi() -> Self {
+        Self::compute_pi(&DecimalContext::current())
+    }
+
+    fn pow(&self, exponent: &Self) -> Self {
+        self.pow_rounded(exponent, &DecimalContext::current())
+    }
+
+    fn is_zero(&self) -> bool {
+        self.sign() == Ordering::Equal && !self.is_nan()
+    }
+
+    fn is_negative(&self) -> bool {
+        self.sign() == Ordering::Less
+    }
+
+    fn is_integer(&self) -> bool {
+        matches!(self, Self::Finite { exponent, .. } if *exponent >= 0)
+    }
+
+    fn is_finite(&self) -> bool {
+        !self.is_nan()
+    }
+
+    fn is_nan(&self) -> bool {
+        matches!(self, Self::NaN)
+    }
+
+    fn apply(name: &str, args: &[Self]) -> Option<Self> {
+        let context = DecimalContext::current();
+        let working = context.guarded(GUARD_DIGITS);
+
+        Some(match (name, args) {
+            ("sqrt", [x]) => x.sqrt(&context),
+            ("exp", [x]) => x.exp(&context),
+            ("ln", [x]) => x.ln(&context),
+            ("log10", [x]) | ("log", [x]) => x
+                .ln(&working)
+                .div_rounded(&Self::new(10, 0).ln(&working), &context),
+            ("log2", [x]) => x
+                .ln(&working)
+                .div_rounded(&Self::new(2, 0).ln(&working), &context),
+            ("log", [x, base]) => x.ln(&working).div_rounded(&base.ln(&working), &context),
+            ("abs", [x]) => x.abs(),
+            ("sign", [x]) if !x.is_nan() => Self::new(x.sign() as i8, 0),
+            ("floor", [x]) => x.round_integer(Rounding::Floor),
+            ("ceil", [x]) => x.round_integer(Rounding::Ceiling),
+            ("round", [x]) => x.round_integer(Rounding::HalfUp),
+            ("trunc", [x]) => x.round_integer(Rounding::Down),
+            ("min", [_, ..]) | ("max", [_, ..]) => {
+                let wanted = if name == "min" {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                let mut best = args[0].clone();
+                for arg in &args[1..] {
+                    match arg.compare(&best)? {
+                        ordering if ordering == wanted => best = arg.clone(),
+                        _ => {}
+                    }
+                }
+                best
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Self::new(value, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimal::{Decimal, DecimalContext, Rounding};
+    use crate::errors::EvalError;
+    use crate::numeric::Numeric;
+    use crate::parser::Parser;
+    use crate::token::tokenize_as;
+
+    fn eval(source: &str, precision: usize, rounding: Rounding) -> Result<String, EvalError> {
+        let expr = Parser::new(tokenize_as::<Decimal>(source).unwrap())
+            .parse()
+            .unwrap();
+        DecimalContext::new(precision, rounding)
+            .eval(&expr)
+            .map(|result| result.to_string())
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(Decimal::from_literal("12.3450"), Decimal::new(12345, -3));
+        assert_eq!(Decimal::from_literal("100"), Decimal::new(1, 2));
+        assert_eq!(Decimal::from_literal(".05"), Decimal::new(5, -2));
+        assert_eq!(Decimal::from_literal("2.5E+3"), Decimal::new(25, 2));
+        assert_eq!(Decimal::from_literal("1e-40"), Decimal::new(1, -40));
+        assert_eq!(Decimal::from_literal("1e99999999999"), Decimal::NaN);
+        assert_eq!(Decimal::from_literal("0e99999999999"), Decimal::new(0, 0));
+        assert_eq!(Decimal::from_f64(0.1), Decimal::new(1, -1));
+        assert_eq!(Decimal::from_f64(-1.5e300), Decimal::new(-15, 299));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Decimal::new(12345, -3).to_string(), "12.345");
+        assert_eq!(Decimal::new(-5, -3).to_string(), "-0.005");
+        assert_eq!(Decimal::new(12, 3).to_string(), "12000");
+        assert_eq!(Decimal::new(12, 30).to_string(), "1.2e31");
+        assert_eq!(Decimal::new(1, -10).to_string(), "1e-10");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let eval = |source| eval(source, 50, Rounding::HalfEven).unwrap();
+
+        assert_eq!(eval("0.1 + 0.2"), "0.3");
+        assert_eq!(eval("1/3"), format!("0.{}", "3".repeat(50)));
+        assert_eq!(eval("2/3"), format!("0.{}7", "6".repeat(49)));
+        assert_eq!(eval("-(1.5 - 2.25) * 4"), "3");
+        assert_eq!(eval("2 ** 100"), "1267650600228229401496703205376");
+        assert_eq!(eval("2 ** -3"), "0.125");
+        assert_eq!(eval("1 + 1/10**60 - 1"), "0");
+        assert_eq!(eval("25!"), "1.5511210043330985984e25");
+        assert_eq!(
+            eval("171!"),
+            "1.2410180702176678234248405241031039926166055775017e309"
+        );
+    }
+
+    #[test]
+    fn rounding_modes() {
+        assert_eq!(eval("0.125", 2, Rounding::HalfEven).unwrap(), "0.12");
+        assert_eq!(eval("0.125", 2, Rounding::HalfUp).unwrap(), "0.13");
+        assert_eq!(eval("0.125", 2, Rounding::HalfDown).unwrap(), "0.12");
+        assert_eq!(eval("0.121", 2, Rounding::Up).unwrap(), "0.13");
+        assert_eq!(eval("0.129", 2, Rounding::Down).unwrap(), "0.12");
+        assert_eq!(eval("-0.121", 2, Rounding::Ceiling).unwrap(), "-0.12");
+        assert_eq!(eval("-0.121", 2, Rounding::Floor).unwrap(), "-0.13");
+        assert_eq!(eval("1/3*3", 5, Rounding::HalfEven).unwrap(), "0.99999");
+        assert_eq!(eval("999.96", 4, Rounding::HalfEven).unwrap(), "1000");
+    }
+
+    #[test]
+    fn constants_and_functions() {
+        let eval = |source| eval(source, 50, Rounding::HalfEven).unwrap();
+
+        assert_eq!(
+            eval("pi"),
+            "3.1415926535897932384626433832795028841971693993751"
+        );
+        assert_eq!(
+            eval("e"),
+            "2.7182818284590452353602874713526624977572470937"
+        );
+        assert_eq!(
+            eval("sqrt(2)"),
+            "1.4142135623730950488016887242096980785696718753769"
+        );
+        assert_eq!(eval("2 ** 0.5"), eval("sqrt(2)"));
+        assert_eq!(eval("exp(1)"), eval("e"));
+        assert_eq!(
+            eval("ln(10)"),
+            "2.3025850929940456840179914546843642076011014886288"
+        );
+        assert_eq!(eval("log(1000)"), "3");
+        assert_eq!(eval("max(1/3, 0.5, -2)"), "0.5");
+        assert_eq!(eval("floor(-2.5) + round(2.5) + trunc(-2.5)"), "-2");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            eval("1/(0.1 + 0.2 - 0.3)", 10, Rounding::HalfEven),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(
+            eval("sqrt(-2)", 10, Rounding::HalfEven),
+            Err(EvalError::Domain("sqrt".into()))
+        );
+        assert_eq!(
+            eval("(-8) ** (1/3)", 10, Rounding::HalfEven),
+            Err(EvalError::Domain("**".into()))
+        );
+    }
+
+    #[test]
+    fn huge_exponents() {
+        let eval = |source| eval(source, 10, Rounding::HalfEven);
+
+        // The exponents overflow without panicking
+        assert_eq!(eval("10**1e19"), Err(EvalError::Domain("**".into())));
+        assert_eq!(
+            eval("(1e1000000000)**1e10"),
+            Err(EvalError::Domain("**".into()))
+        );
+        assert_eq!(
+            eval("1e2000000000 * 1e2000000000"),
+            Err(EvalError::Domain("*".into()))
+        );
+        assert_eq!(
+            eval("1e-2000000000 / 1e2000000000"),
+            Err(EvalError::Domain("/".into()))
+        );
+
+        // Unless the base is 0 or ±1, or the result underflows
+        assert_eq!(eval("(-1)**1e30"), Ok("1".into()));
+        assert_eq!(eval("(-1)**10000000000000000001"), Ok("-1".into()));
+        assert_eq!(eval("0.5**1e19"), Ok("0".into()));
+        assert_eq!(eval("0**1e19"), Ok("0".into()));
+    }
+}
@@ -0,0 +1,370 @@
+//! # Derivative module
+//! Contains the symbolic differentiation of an [Expr].
+//!
+//! The result is passed through [simplify] so it stays readable.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::derivative::differentiate;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("3x**2 + sin(2x)").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! let derivative = differentiate(&expr, "x").unwrap();
+//! assert_eq!(derivative.to_string(), "6 * x + 2 * cos(2 * x)");
+//! # }
+//! ```
+
+use crate::ast::{BinOpType, Expr, UnaryOpType};
+use crate::errors::DiffError;
+use crate::eval::{builtin, relative_percentage};
+use crate::numeric::Numeric;
+use crate::simplify::simplify;
+
+/// Derivative of `expr` with respect to the variable `var`.
+///
+/// Functions are differentiated with the chain rule, the rounding functions and `sign`
+/// have a null derivative wherever they are differentiable. `min`, `max` and the
+/// factorial can't be differentiated.
+pub fn differentiate<N: Numeric>(expr: &Expr<N>, var: &str) -> Result<Expr<N>, DiffError> {
+    derive(expr, var).map(|derivative| simplify(&derivative))
+}
+
+/// Unsimplified derivative of `expr`.
+fn derive<N: Numeric>(expr: &Expr<N>, var: &str) -> Result<Expr<N>, DiffError> {
+    Ok(match expr {
+        Expr::Number(_) | Expr::Quantity(..) | Expr::E | Expr::Pi | Expr::I => number(0.),
+        Expr::Variable(name) => number(if name == var { 1. } else { 0. }),
+        Expr::UnaryOp(UnaryOpType::Negate, operand) => negate(derive(operand, var)?),
+        Expr::UnaryOp(UnaryOpType::Noop, operand) => derive(operand, var)?,
+        // Piecewise constant, null wherever it is differentiable
+        Expr::UnaryOp(UnaryOpType::Not, _) => number(0.),
+        Expr::BinOp(_, op, _) if op.is_boolean() || *op == BinOpType::FloorDiv => number(0.),
+        Expr::BinOp(left, op @ (BinOpType::Add | BinOpType::Sub), right)
+            if right.percentage().is_some() =>
+        {
+            let percent = right.percentage().unwrap();
+            derive(&relative_percentage(left, *op, percent), var)?
+        }
+        Expr::UnaryOp(UnaryOpType::Percent, operand) => {
+            quotient(derive(operand, var)?, number(100.))
+        }
+        Expr::UnaryOp(UnaryOpType::Factorial, _) => {
+            return Err(DiffError::NotDifferentiable("!".into()))
+        }
+        Expr::BinOp(left, op, right) => {
+            let (left, right) = (left.as_ref(), right.as_ref());
+            let d_left = derive(left, var)?;
+            let d_right = derive(right, var)?;
+
+            match op {
+                BinOpType::Add => sum(d_left, d_right),
+                BinOpType::Sub => difference(d_left, d_right),
+                // a % b = a - b * (a // b), the floor division being piecewise constant
+                BinOpType::Mod => difference(
+                    d_left,
+                    product(
+                        d_right,
+                        Expr::BinOp(
+                            left.clone().boxed(),
+                            BinOpType::FloorDiv,
+                            right.clone().boxed(),
+                        ),
+                    ),
+                ),
+                BinOpType::Mul => sum(
+                    product(d_left, right.clone()),
+                    product(left.clone(), d_right),
+                ),
+                BinOpType::Div => quotient(
+                    difference(
+                        product(d_left, right.clone()),
+                        product(left.clone(), d_right),
+                    ),
+                    power(right.clone(), number(2.)),
+                ),
+                BinOpType::Pow if !depends_on(right, var) => product(
+                    product(
+                        right.clone(),
+                        power(left.clone(), difference(right.clone(), number(1.))),
+                    ),
+                    d_left,
+                ),
+                BinOpType::Pow if !depends_on(left, var) => {
+                    let log = match left {
+                        Expr::E => number(1.),
+                        _ => call("ln", vec![left.clone()]),
+                    };
+                    product(product(expr.clone(), log), d_right)
+                }
+                // d(u ** v) = u ** v * (v' ln(u) + v u' / u)
+                BinOpType::Pow => product(
+                    expr.clone(),
+                    sum(
+                        product(d_right, call("ln", vec![left.clone()])),
+                        quotient(product(right.clone(), d_left), left.clone()),
+                    ),
+                ),
+                // Piecewise constant, see above
+                _ => unreachable!(),
+            }
+        }
+        Expr::Call(name, args) => differentiate_call(name, args, var)?,
+        // The variables are dimensionless, the derivative has the unit of the value unless
+        // it is null, a plain zero that converts to no unit
+        Expr::Convert(value, target) => match simplify(&derive(value, var)?) {
+            Expr::Number(derivative) if derivative.is_zero() => number(0.),
+            derivative => Expr::Convert(derivative.boxed(), target.clone()),
+        },
+        // Wherever the condition doesn't change
+        Expr::Conditional(condition, then, otherwise) => Expr::Conditional(
+            condition.clone(),
+            derive(then, var)?.boxed(),
+            derive(otherwise, var)?.boxed(),
+        ),
+    })
+}
+
+/// Chain rule, the derivative of `f(u)` is `f'(u) * u'`.
+fn differentiate_call<N: Numeric>(
+    name: &str,
+    args: &[Expr<N>],
+    var: &str,
+) -> Result<Expr<N>, DiffError> {
+    let builtin = builtin(name).ok_or_else(|| DiffError::UnknownFunction(name.into()))?;
+    if !builtin.arity.accepts(args.len()) {
+        return Err(DiffError::WrongArity {
+            name: name.into(),
+            expected: builtin.arity,
+            got: args.len(),
+        });
+    }
+
+    let (u, outer) = match args {
+        [u] => (u, derivative_of(name, u.clone())),
+        // atan2(y, x) = atan(y / x) up to a constant on each quadrant
+        [y, x] if name == "atan2" => {
+            let d_y = derive(y, var)?;
+            let d_x = derive(x, var)?;
+            return Ok(quotient(
+                difference(product(x.clone(), d_y), product(y.clone(), d_x)),
+                sum(power(x.clone(), number(2.)), power(y.clone(), number(2.))),
+            ));
+        }
+        [x, base] if name == "log" => {
+            let ln = |arg: &Expr<N>| call("ln", vec![arg.clone()]);
+            return derive(&quotient(ln(x), ln(base)), var);
+        }
+        _ => return Err(DiffError::NotDifferentiable(name.into())),
+    };
+
+    let outer = outer.ok_or_else(|| DiffError::NotDifferentiable(name.into()))?;
+    Ok(product(outer, derive(u, var)?))
+}
+
+/// Derivative of the unary function `name` at `u`.
+fn derivative_of<N: Numeric>(name: &str, u: Expr<N>) -> Option<Expr<N>> {
+    let squared = |u: Expr<N>| power(u, number(2.));
+    let inverse = |expr: Expr<N>| quotient(number(1.), expr);
+    let sqrt = |expr: Expr<N>| call("sqrt", vec![expr]);
+
+    Some(match name {
+        "sin" => call("cos", vec![u]),
+        "cos" => negate(call("sin", vec![u])),
+        "tan" => inverse(squared(call("cos", vec![u]))),
+        "asin" => inverse(sqrt(difference(number(1.), squared(u)))),
+        "acos" => negate(inverse(sqrt(difference(number(1.), squared(u))))),
+        "atan" => inverse(sum(number(1.), squared(u))),
+        "sinh" => call("cosh", vec![u]),
+        "cosh" => call("sinh", vec![u]),
+        "tanh" => inverse(squared(call("cosh", vec![u]))),
+        "asinh" => inverse(sqrt(sum(squared(u), number(1.)))),
+        "acosh" => inverse(sqrt(difference(squared(u), number(1.)))),
+        "atanh" => inverse(difference(number(1.), squared(u))),
+        "exp" => call("exp", vec![u]),
+        "sqrt" => inverse(product(number(2.), sqrt(u))),
+        "cbrt" => inverse(product(number(3.), squared(call("cbrt", vec![u])))),
+        "ln" => inverse(u),
+        "log2" => inverse(product(u, call("ln", vec![number(2.)]))),
+        "log10" | "log" => inverse(product(u, call("ln", vec![number(10.)]))),
+        "abs" => call("sign", vec![u]),
+        "floor" | "ceil" | "round" | "trunc" | "sign" => number(0.),
+        _ => return None,
+    })
+}
+
+/// Whether the value of `expr` depends on the variable `var`.
+fn depends_on<N>(expr: &Expr<N>, var: &str) -> bool {
+    match expr {
+        Expr::Variable(name) => name == var,
+        Expr::UnaryOp(_, operand) => depends_on(operand, var),
+        Expr::BinOp(left, _, right) => depends_on(left, var) || depends_on(right, var),
+        Expr::Call(_, args) => args.iter().any(|arg| depends_on(arg, var)),
+        Expr::Convert(value, _) => depends_on(value, var),
+        Expr::Conditional(condition, then, otherwise) => {
+            depends_on(condition, var) || depends_on(then, var) || depends_on(otherwise, var)
+        }
+        Expr::Number(_) | Expr::Quantity(..) | Expr::E | Expr::Pi | Expr::I => false,
+    }
+}
+
+fn number<N: Numeric>(value: f64) -> Expr<N> {
+    Expr::Number(N::from_f64(value))
+}
+
+fn call<N>(name: &str, args: Vec<Expr<N>>) -> Expr<N> {
+    Expr::Call(name.into(), args)
+}
+
+fn negate<N>(expr: Expr<N>) -> Expr<N> {
+    Expr::UnaryOp(UnaryOpType::Negate, expr.boxed())
+}
+
+fn sum<N>(left: Expr<N>, right: Expr<N>) -> Expr<N> {
+    Expr::BinOp(left.boxed(), BinOpType::Add, right.boxed())
+}
+
+fn difference<N>(left: Expr<N>, right: Expr<N>) -> Expr<N> {
+    Expr::BinOp(left.boxed(), BinOpType::Sub, right.boxed())
+}
+
+fn product<N>(left: Expr<N>, right: Expr<N>) -> Expr<N> {
+    Expr::BinOp(left.boxed(), BinOpType::Mul, right.boxed())
+}
+
+fn quotient<N>(left: Expr<N>, right: Expr<N>) -> Expr<N> {
+    Expr::BinOp(left.boxed(), BinOpType::Div, right.boxed())
+}
+
+fn power<N>(base: Expr<N>, exponent: Expr<N>) -> Expr<N> {
+    Expr::BinOp(base.boxed(), BinOpType::Pow, exponent.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::derivative::differentiate;
+    use crate::errors::DiffError;
+    use crate::eval::{Arity, Environment, Eval};
+    use crate::parser::Parser;
+    use crate::token::tokenize;
+
+    fn derive(source: &str) -> Result<String, DiffError> {
+        let expr = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+        differentiate(&expr, "x").map(|derivative| derivative.to_string())
+    }
+
+    #[test]
+    fn differentiate_operators() {
+        assert_eq!(derive("42 + y"), Ok("0".into()));
+        assert_eq!(derive("x"), Ok("1".into()));
+        assert_eq!(derive("-x"), Ok("-1".into()));
+        assert_eq!(derive("3x**2 + 2x + 1"), Ok("6 * x + 2".into()));
+        assert_eq!(derive("x * y"), Ok("y".into()));
+        assert_eq!(derive("x / y"), Ok("1 / y".into()));
+        assert_eq!(derive("1 / x"), Ok("-(1 / x ** 2)".into()));
+        assert_eq!(derive("x ** -1"), Ok("-(1 / x ** 2)".into()));
+        assert_eq!(derive("e ** x"), Ok("e ** x".into()));
+        assert_eq!(derive("2 ** x"), Ok("2 ** x * ln(2)".into()));
+        assert_eq!(derive("x ** x"), Ok("x ** x * (ln(x) + 1)".into()));
+        assert_eq!(derive("(x > 1) + not x"), Ok("0".into()));
+        assert_eq!(derive("x > 1 ? x**2 : -x"), Ok("x > 1 ? 2 * x : -1".into()));
+        assert_eq!(derive("x % 3 + x // 3"), Ok("1".into()));
+        assert_eq!(derive("x - 10%"), Ok("0.9".into()));
+        assert_eq!(derive("3 * x%"), Ok("0.03".into()));
+        assert_eq!(derive("x!"), Err(DiffError::NotDifferentiable("!".into())));
+    }
+
+    #[test]
+    fn differentiate_calls() {
+        assert_eq!(derive("sin(x ** 2)"), Ok("2 * x * cos(x ** 2)".into()));
+        assert_eq!(derive("cos(y)"), Ok("0".into()));
+        assert_eq!(derive("ln(2x)"), Ok("1 / x".into()));
+        assert_eq!(derive("sqrt(x)"), Ok("1 / (2 * sqrt(x))".into()));
+        assert_eq!(derive("abs(x) + floor(x)"), Ok("sign(x)".into()));
+        assert_eq!(
+            derive("max(x, 1)"),
+            Err(DiffError::NotDifferentiable("max".into()))
+        );
+        assert_eq!(derive("f(x)"), Err(DiffError::UnknownFunction("f".into())));
+    }
+
+    #[test]
+    fn differentiate_conversions() {
+        assert_eq!(derive("1 km in m"), Ok("0".into()));
+        assert_eq!(derive("x + (2 km + 1 m in m)"), Ok("1".into()));
+        assert_eq!(derive("x * 2 km in m"), Ok("2 km in m".into()));
+
+        let expr = Parser::new(tokenize("x ** 2 * 1 km in m").unwrap())
+            .parse()
+            .unwrap();
+        let env = vec![("x", 3.)].into_iter().collect::<Environment>();
+        let derivative = differentiate(&expr, "x").unwrap();
+        assert_eq!(
+            derivative.eval_quantity_in(&env).unwrap().to_string(),
+            "6000 m"
+        );
+    }
+
+    #[test]
+    fn differentiate_wrong_arity() {
+        assert_eq!(
+            derive("sin()"),
+            Err(DiffError::WrongArity {
+                name: "sin".into(),
+                expected: Arity::exactly(1),
+                got: 0,
+            })
+        );
+        assert_eq!(
+            derive("max()"),
+            Err(DiffError::WrongArity {
+                name: "max".into(),
+                expected: Arity::at_least(1),
+                got: 0,
+            })
+        );
+        assert!(derive("sin(x, 2)").is_err());
+    }
+
+    #[test]
+    fn derivative_values() {
+        // Compare with a central difference at a few points
+        let sources = [
+            "x ** 3 - 2x",
+            "sin(x) * exp(-x)",
+            "x ** x",
+            "atan2(x, 2)",
+            "log(x ** 2 + 1, 3)",
+            "tanh(x) / (1 + x ** 2)",
+            "acos(x / 4) + cbrt(x)",
+        ];
+
+        for source in sources.iter() {
+            let expr = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+            let derivative = differentiate(&expr, "x").unwrap();
+
+            for x in [0.5, 1.5, 2.5].iter() {
+                let at = |x: f64| {
+                    let env = vec![("x", x)].into_iter().collect::<Environment>();
+                    expr.eval_in(&env).unwrap()
+                };
+                let h = 1e-6;
+                let expected = (at(x + h) - at(x - h)) / (2. * h);
+
+                let env = vec![("x", *x)].into_iter().collect();
+                let actual = derivative.eval_in(&env).unwrap();
+                assert!(
+                    (actual - expected).abs() < 1e-5,
+                    "d/dx {} at {}: {} != {}",
+                    source,
+                    x,
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+}
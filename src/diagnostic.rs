@@ -0,0 +1,257 @@
+//! # Diagnostic module
+//! Contains the logic to render an error as a snippet of the input with the
+//! offending region underlined, in the style of rustc.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::diagnostic::Diagnostic;
+//! # use rust_calculator::span::Span;
+//! let diagnostic = Diagnostic::new("1+2)", Span::new(3, 4), "Mismatched parenthesis !")
+//!     .with_label("this ')' has no matching '('");
+//!
+//! assert_eq!(
+//!     diagnostic.to_string(),
+//!     concat!(
+//!         "error: Mismatched parenthesis !\n",
+//!         " --> 1:4\n",
+//!         "  |\n",
+//!         "1 | 1+2)\n",
+//!         "  |    ^ this ')' has no matching '('\n",
+//!     )
+//! );
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::errors::{Error, EvalError, ParserError};
+use crate::span::Span;
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic<'a> {
+    source: &'a str,
+    span: Span,
+    message: String,
+    label: String,
+    /// Number of the first line of `source`, when it is an excerpt of a larger text.
+    first_line: usize,
+}
+
+/// Position of the span in the source.
+struct Location {
+    start: usize,
+    end: usize,
+    line_start: usize,
+    line_end: usize,
+    line_number: usize,
+    /// Counted in chars from 0.
+    column: usize,
+}
+
+impl<'a> Diagnostic<'a> {
+    pub fn new(source: &'a str, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            source,
+            span,
+            message: message.into(),
+            label: String::new(),
+            first_line: 1,
+        }
+    }
+
+    /// Build the diagnostic of a [ParserError] raised while reading `source`.
+    pub fn from_parser_error(source: &'a str, error: &ParserError) -> Self {
+        Self::new(source, error.span(), error.to_string()).with_label(error.label())
+    }
+
+    /// Build the diagnostic of an [EvalError] raised at `span`, the whole `source` is
+    /// underlined if it isn't located.
+    pub fn from_eval_error(source: &'a str, error: &EvalError, span: Option<Span>) -> Self {
+        match span {
+            Some(span) => Self::new(source, span, error.to_string()).with_label(error.label()),
+            None => Self::new(source, Span::new(0, source.len()), error.to_string())
+                .with_label("while evaluating this expression"),
+        }
+    }
+
+    pub fn from_error(source: &'a str, error: &Error) -> Self {
+        match error {
+            Error::Parser(e) => Self::from_parser_error(source, e),
+            Error::Eval(e, span) => Self::from_eval_error(source, e, *span),
+        }
+    }
+
+    /// Short text displayed next to the underline.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Number the lines of the source from `first_line` instead of 1.
+    pub fn with_first_line(mut self, first_line: usize) -> Self {
+        self.first_line = first_line;
+        self
+    }
+
+    /// Line and column of the start of the span, both counted from 1.
+    pub fn location(&self) -> (usize, usize) {
+        let location = self.locate();
+        (location.line_number, location.column + 1)
+    }
+
+    /// Render on a single line as `line:column: error: message`.
+    pub fn to_short_string(&self) -> String {
+        let (line, column) = self.location();
+        format!("{}:{}: error: {}", line, column, self.message)
+    }
+
+    fn locate(&self) -> Location {
+        // Clamp the span to the source, some errors point right after the end of the input
+        let start = self.span.start.min(self.source.len());
+        let end = self.span.end.clamp(start, self.source.len());
+
+        // Find the line containing the start of the span
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+        let line_number = self.source[..line_start].matches('\n').count() + self.first_line;
+
+        // Columns are counted in chars
+        let column = self.source[line_start..start].chars().count();
+
+        Location {
+            start,
+            end,
+            line_start,
+            line_end,
+            line_number,
+            column,
+        }
+    }
+}
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Location {
+            start,
+            end,
+            line_start,
+            line_end,
+            line_number,
+            column,
+        } = self.locate();
+        let line = &self.source[line_start..line_end];
+
+        // The span may continue on the next lines
+        let width = self.source[start..end.min(line_end)].chars().count().max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{}--> {}:{}", gutter, line_number, column + 1)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_number, line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(column),
+            "^".repeat(width)
+        )?;
+        if !self.label.is_empty() {
+            write!(f, " {}", self.label)?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Diagnostic;
+    use crate::eval::Environment;
+    use crate::parser::Parser;
+    use crate::span::Span;
+    use crate::token::tokenize;
+
+    #[test]
+    fn underline_span() {
+        let diagnostic = Diagnostic::new("1 + 2 ** pi", Span::new(6, 8), "Oops").with_label("here");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: Oops\n --> 1:7\n  |\n1 | 1 + 2 ** pi\n  |       ^^ here\n"
+        );
+    }
+
+    #[test]
+    fn underline_eval_error() {
+        let underline = |source: &str| {
+            let program = Parser::new(tokenize(source).unwrap())
+                .parse_program()
+                .unwrap();
+            let error = program
+                .eval_located_in(&mut Environment::new())
+                .unwrap_err();
+            Diagnostic::from_error(source, &error).to_string()
+        };
+
+        assert_eq!(
+            underline("1 + 1/0"),
+            "error: Division by zero !\n --> 1:6\n  |\n1 | 1 + 1/0\n  |      ^ the divisor is zero\n"
+        );
+        assert_eq!(
+            underline("x = 2; 1 + sqrt(-4)"),
+            "error: Argument out of the domain of 'sqrt'\n --> 1:12\n  |\n1 | x = 2; 1 + sqrt(-4)\n  |            ^^^^^^^^ an argument is out of its domain\n"
+        );
+        assert_eq!(
+            underline("(1 m + 1 s) * 2"),
+            "error: Incompatible units, expected m but got s\n --> 1:6\n  |\n1 | (1 m + 1 s) * 2\n  |      ^ the units of the operands don't match\n"
+        );
+        assert_eq!(
+            underline("x = 2; y = 1 < x ? max(x, 1 % 0) : 0"),
+            "error: Division by zero !\n --> 1:29\n  |\n1 | x = 2; y = 1 < x ? max(x, 1 % 0) : 0\n  |                             ^ the divisor is zero\n"
+        );
+        assert_eq!(
+            underline("f(x) = x + y\n2 * f(1)"),
+            "error: Unbound variable: y\n --> 2:5\n  |\n2 | 2 * f(1)\n  |     ^^^^ not bound to any value\n"
+        );
+    }
+
+    #[test]
+    fn underline_end_of_input() {
+        let source = "2 *";
+        let error = Parser::new(tokenize(source).unwrap()).parse().unwrap_err();
+        let diagnostic = Diagnostic::from_parser_error(source, &error);
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: Not enough operands in the expression !\n --> 1:3\n  |\n1 | 2 *\n  |   ^ this operator is missing an operand\n"
+        );
+
+        let diagnostic = Diagnostic::new("1+", Span::new(2, 2), "Oops");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: Oops\n --> 1:3\n  |\n1 | 1+\n  |   ^\n"
+        );
+    }
+
+    #[test]
+    fn underline_multiline() {
+        let diagnostic = Diagnostic::new("1 +\n(2 *\n3", Span::new(4, 9), "Oops");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: Oops\n --> 2:1\n  |\n2 | (2 *\n  | ^^^^\n"
+        );
+    }
+
+    #[test]
+    fn excerpt_line_numbers() {
+        let diagnostic = Diagnostic::new("1 + é)", Span::new(6, 7), "Oops").with_first_line(12);
+        assert_eq!(diagnostic.location(), (12, 6));
+        assert_eq!(diagnostic.to_short_string(), "12:6: error: Oops");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: Oops\n  --> 12:6\n   |\n12 | 1 + é)\n   |      ^\n"
+        );
+    }
+}
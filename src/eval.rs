@@ -1,44 +1,890 @@
 //! # Eval module
 //! Contains the meaning of the different variants of Expr and operators.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::eval::{Environment, Eval};
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("2x + 1").unwrap()).parse().unwrap();
+//!
+//! let mut env = Environment::new();
+//! for x in 0..3 {
+//!     env.set("x", x as f64);
+//!     assert_eq!(expr.eval_in(&env), Ok(2. * x as f64 + 1.));
+//! }
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter};
+use std::iter::FromIterator;
 
 use crate::ast::{BinOpType, Expr, Number, UnaryOpType};
+use crate::errors::{EvalError, EvalResult};
+use crate::numeric::Numeric;
+use crate::units::{call_dimension, unit_quantity, Dimension, Quantity};
+
+pub trait Eval<N: Numeric = Number> {
+    /// Evaluate following IEEE 754 semantics without any variable bound,
+    /// so `1/0` is infinite and unbound variables evaluate to NaN.
+    fn eval(&self) -> N;
+
+    /// Evaluate with the variables bound in `env`, divisions by zero, arguments out of
+    /// the domain of a function, overflows and non-finite values are reported as errors.
+    fn eval_in(&self, env: &Environment<N>) -> EvalResult<N>;
+
+    /// Same as [Eval#eval_in] without any variable bound.
+    fn try_eval(&self) -> EvalResult<N> {
+        self.eval_in(&Environment::new())
+    }
+
+    /// Same as [Eval#eval_in], keeping the dimension and unit of the result.
+    fn eval_quantity_in(&self, env: &Environment<N>) -> EvalResult<Quantity<N>>;
+
+    /// Same as [Eval#eval_quantity_in] without any variable bound.
+    fn try_eval_quantity(&self) -> EvalResult<Quantity<N>> {
+        self.eval_quantity_in(&Environment::new())
+    }
+}
+
+/// Largest number of nested calls of user-defined functions.
+pub const MAX_CALL_DEPTH: usize = 100;
+
+/// Values of the variables and user-defined functions available during an evaluation.
+#[derive(Debug, Clone)]
+pub struct Environment<N = Number> {
+    variables: HashMap<String, N>,
+    /// Dimensions of the variables that aren't dimensionless, their values are in the
+    /// SI base units.
+    dimensions: HashMap<String, Dimension>,
+    functions: HashMap<String, Function<N>>,
+}
+
+impl<N> Default for Environment<N> {
+    fn default() -> Self {
+        Self {
+            variables: HashMap::new(),
+            dimensions: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+}
+
+/// A user-defined function, like `f(x, y) = x**2 + y`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Function<N = Number> {
+    pub params: Vec<String>,
+    /// Sees the parameters over the variables of the environment.
+    pub body: Expr<N>,
+}
+
+impl<N: Display> Display for Function<N> {
+    /// Formatted as the right-hand side of its definition, like `(x, y) = x ** 2 + y`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}) = {}", self.params.join(", "), self.body)
+    }
+}
+
+impl<N: Clone> Environment<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind `name` to `value`, returns the previous value if any.
+    pub fn set(&mut self, name: impl Into<String>, value: N) -> Option<N> {
+        let name = name.into();
+        self.dimensions.remove(&name);
+        self.variables.insert(name, value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<N> {
+        self.variables.get(name).cloned()
+    }
+
+    /// Dimension of the variable `name`, dimensionless if unbound.
+    pub fn dimension(&self, name: &str) -> Dimension {
+        self.dimensions.get(name).copied().unwrap_or_default()
+    }
+
+    /// Bound variables sorted by name.
+    pub fn variables(&self) -> Vec<(&str, &N)> {
+        let mut variables = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect::<Vec<_>>();
+        variables.sort_by_key(|(name, _)| *name);
+        variables
+    }
+
+    /// Define the function `name`, shadowing the builtin of the same name if any.
+    /// Returns the previous definition if any.
+    pub fn define(
+        &mut self,
+        name: impl Into<String>,
+        function: Function<N>,
+    ) -> Option<Function<N>> {
+        self.functions.insert(name.into(), function)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function<N>> {
+        self.functions.get(name)
+    }
+
+    /// User-defined functions sorted by name.
+    pub fn functions(&self) -> Vec<(&str, &Function<N>)> {
+        let mut functions = self
+            .functions
+            .iter()
+            .map(|(name, function)| (name.as_str(), function))
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|(name, _)| *name);
+        functions
+    }
+}
+
+impl<N: Numeric> Environment<N> {
+    /// Bind `name` to `quantity` in the SI base units, keeping its dimension.
+    pub fn set_quantity(&mut self, name: impl Into<String>, quantity: Quantity<N>) {
+        let name = name.into();
+        self.set(name.clone(), quantity.si_value());
+        if !quantity.dimension.is_none() {
+            self.dimensions.insert(name, quantity.dimension);
+        }
+    }
+}
+
+impl<N, S: Into<String>> FromIterator<(S, N)> for Environment<N> {
+    fn from_iter<T: IntoIterator<Item = (S, N)>>(iter: T) -> Self {
+        Self {
+            variables: iter
+                .into_iter()
+                .map(|(name, value)| (name.into(), value))
+                .collect(),
+            dimensions: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+}
+
+impl<N: Numeric> Eval<N> for Expr<N> {
+    fn eval(&self) -> N {
+        evaluate(self, &Scope::global(&Environment::new()), false)
+            .map(|quantity| quantity.value)
+            .unwrap_or_else(|_| N::from_f64(f64::NAN))
+    }
+
+    fn eval_in(&self, env: &Environment<N>) -> EvalResult<N> {
+        self.eval_quantity_in(env).map(|quantity| quantity.value)
+    }
+
+    fn eval_quantity_in(&self, env: &Environment<N>) -> EvalResult<Quantity<N>> {
+        evaluate_located(self, env).map_err(|located| located.error)
+    }
+}
+
+/// An evaluation error with the node of the expression raising it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Located {
+    pub error: EvalError,
+    /// Index of the node in the post-order of the expression, `None` for the root.
+    node: Option<usize>,
+}
+
+impl Located {
+    /// Index of the node raising the error in the post-order of `expr`.
+    pub fn node(&self, expr: &Expr<impl Sized>) -> usize {
+        self.node.unwrap_or(expr.size() - 1)
+    }
+
+    /// Locate an error raised by the operand `operand` of an expression, `offset` is the
+    /// number of nodes of the operands before it.
+    fn within(self, operand: &Expr<impl Sized>, offset: usize) -> Self {
+        Self {
+            node: Some(offset + self.node(operand)),
+            error: self.error,
+        }
+    }
+}
+
+impl From<EvalError> for Located {
+    fn from(error: EvalError) -> Self {
+        Self { error, node: None }
+    }
+}
+
+/// Same as [Eval#eval_quantity_in], locating the node raising the error.
+pub(crate) fn evaluate_located<N: Numeric>(
+    expr: &Expr<N>,
+    env: &Environment<N>,
+) -> Result<Quantity<N>, Located> {
+    evaluate(expr, &Scope::global(env), true)
+}
+
+/// The variables visible from an expression: the parameters of the user-defined
+/// function it is the body of, if any, over the variables of the environment.
+struct Scope<'a, N> {
+    env: &'a Environment<N>,
+    params: &'a [String],
+    args: Vec<Quantity<N>>,
+    /// Number of calls of user-defined functions up to this one.
+    depth: usize,
+    /// Whether the names are units, in the target of a conversion.
+    units: bool,
+}
+
+impl<'a, N: Numeric> Scope<'a, N> {
+    fn global(env: &'a Environment<N>) -> Self {
+        Self {
+            env,
+            params: &[],
+            args: Vec::new(),
+            depth: 0,
+            units: false,
+        }
+    }
+
+    /// Value of the variable `name`, with its dimension.
+    fn get(&self, name: &str) -> Option<Quantity<N>> {
+        match self.params.iter().position(|param| param == name) {
+            Some(index) => Some(self.args[index].clone()),
+            None => {
+                let value = self.env.get(name)?;
+                Some(Quantity::in_si(value, self.env.dimension(name)))
+            }
+        }
+    }
+}
+
+/// Walk the expression, `checked` turns the special values of IEEE 754 into errors.
+fn evaluate<N: Numeric>(
+    expr: &Expr<N>,
+    scope: &Scope<N>,
+    checked: bool,
+) -> Result<Quantity<N>, Located> {
+    // Evaluate an operand preceded by `offset` nodes in the other operands
+    let operand = |operand: &Expr<N>, offset: usize, scope: &Scope<N>| {
+        evaluate(operand, scope, checked).map_err(|e| e.within(operand, offset))
+    };
+
+    Ok(match expr {
+        Expr::Number(num) => Quantity::new(check_finite(num.clone(), checked)?),
+        Expr::Quantity(num, name) => {
+            let unit = unit_quantity::<N>(name)?;
+            let value = check_finite(num.clone(), checked)? * unit.value;
+            Quantity::in_si(value, unit.dimension)
+        }
+        Expr::Variable(name) if scope.units => unit_quantity(name)?,
+        Expr::Variable(name) => {
+            let value = scope
+                .get(name)
+                .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?;
+            Quantity {
+                value: check_finite(value.value, checked)?,
+                ..value
+            }
+        }
+        Expr::E => Quantity::new(N::e()),
+        Expr::Pi => Quantity::new(N::pi()),
+        Expr::I => Quantity::new(N::i().ok_or(EvalError::NotComplex)?),
+        Expr::UnaryOp(UnaryOpType::Negate, value) => {
+            let operand = operand(value, 0, scope)?;
+            Quantity::in_si(-operand.si_value(), operand.dimension)
+        }
+        Expr::UnaryOp(UnaryOpType::Noop, value) => operand(value, 0, scope)?,
+        Expr::UnaryOp(UnaryOpType::Not, value) => {
+            Quantity::new(boolean(operand(value, 0, scope)?.value.is_zero()))
+        }
+        Expr::UnaryOp(UnaryOpType::Factorial, value) => {
+            let operand = operand(value, 0, scope)?;
+            operand.expect(Dimension::NONE)?;
+            Quantity::new(factorial(operand.value, checked)?)
+        }
+        Expr::UnaryOp(UnaryOpType::Percent, value) => {
+            let operand = operand(value, 0, scope)?;
+            let value = apply_binary(
+                BinOpType::Div,
+                operand.si_value(),
+                N::from_literal("100"),
+                checked,
+            )?;
+            Quantity::in_si(value, operand.dimension)
+        }
+        Expr::Call(name, args) if scope.env.function(name).is_some() => {
+            let function = scope.env.function(name).unwrap();
+            if args.len() != function.params.len() {
+                return Err(EvalError::WrongArity {
+                    name: name.clone(),
+                    expected: Arity::exactly(function.params.len()),
+                    got: args.len(),
+                }
+                .into());
+            }
+            if scope.depth >= MAX_CALL_DEPTH {
+                return Err(EvalError::RecursionLimit(MAX_CALL_DEPTH).into());
+            }
+
+            // Lexical scoping, the body only sees its parameters over the globals
+            let callee = Scope {
+                env: scope.env,
+                params: &function.params,
+                args: arguments(args, |arg, offset| operand(arg, offset, scope))?,
+                depth: scope.depth + 1,
+                units: false,
+            };
+            // The errors of the body are located at the call
+            evaluate(&function.body, &callee, checked).map_err(|e| e.error)?
+        }
+        Expr::Call(name, args) => {
+            let builtin = resolve_builtin(name, args.len())?;
+            let args = arguments(args, |arg, offset| operand(arg, offset, scope))?;
+
+            let dimensions = args.iter().map(|arg| arg.dimension).collect::<Vec<_>>();
+            let dimension = call_dimension(name, &dimensions)?;
+            let values = args.iter().map(Quantity::si_value).collect::<Vec<_>>();
+            Quantity::in_si(apply_builtin(builtin, &values, checked)?, dimension)
+        }
+        // Short-circuit, the right operand is only evaluated if it decides the result
+        Expr::BinOp(left, op @ (BinOpType::And | BinOpType::Or), right) => {
+            let offset = left.size();
+            let left = !operand(left, 0, scope)?.value.is_zero();
+            let result = match op {
+                BinOpType::And if !left => false,
+                BinOpType::Or if left => true,
+                _ => !operand(right, offset, scope)?.value.is_zero(),
+            };
+            Quantity::new(boolean(result))
+        }
+        Expr::BinOp(left, op @ (BinOpType::Add | BinOpType::Sub), right)
+            if right.percentage().is_some() =>
+        {
+            let percent = right.percentage().unwrap();
+            // The rewritten expression has other nodes, its errors are located at `op`
+            evaluate(&relative_percentage(left, *op, percent), scope, checked)
+                .map_err(|e| e.error)?
+        }
+        Expr::BinOp(left, op, right) => {
+            let (left, right) = (
+                operand(left, 0, scope)?,
+                operand(right, left.size(), scope)?,
+            );
+            let dimension = match op {
+                BinOpType::Add | BinOpType::Sub | BinOpType::Mod => {
+                    right.expect(left.dimension)?;
+                    left.dimension
+                }
+                BinOpType::Mul => (left.dimension * right.dimension).ok_or(EvalError::Overflow)?,
+                BinOpType::Div | BinOpType::FloorDiv => {
+                    (left.dimension / right.dimension).ok_or(EvalError::Overflow)?
+                }
+                BinOpType::Eq
+                | BinOpType::Ne
+                | BinOpType::Lt
+                | BinOpType::Le
+                | BinOpType::Gt
+                | BinOpType::Ge => {
+                    right.expect(left.dimension)?;
+                    Dimension::NONE
+                }
+                BinOpType::And | BinOpType::Or => Dimension::NONE,
+                BinOpType::Pow => {
+                    right.expect(Dimension::NONE)?;
+                    if left.dimension.is_none() {
+                        Dimension::NONE
+                    } else {
+                        let exponent = right.si_value().to_f64();
+                        left.dimension
+                            .pow(exponent)
+                            .ok_or(EvalError::IncompatibleUnits {
+                                expected: Dimension::NONE,
+                                got: left.dimension,
+                            })?
+                    }
+                }
+            };
+            let value = apply_binary(*op, left.si_value(), right.si_value(), checked)?;
+            Quantity::in_si(value, dimension)
+        }
+        Expr::Convert(value, target) => {
+            let offset = value.size();
+            let value = operand(value, 0, scope)?;
+            if !is_unit(target) {
+                return Err(EvalError::NotAUnit(target.to_string()).into());
+            }
+            // The variables don't shadow the units of the target
+            let env = Environment::new();
+            let units = Scope {
+                units: true,
+                ..Scope::global(&env)
+            };
+            let unit = operand(target, offset, &units)?;
+            value.expect(unit.dimension)?;
+
+            let factor = unit.si_value();
+            Quantity {
+                value: apply_binary(BinOpType::Div, value.si_value(), factor.clone(), checked)?,
+                dimension: value.dimension,
+                unit: Some((target.to_string().replace(' ', ""), factor)),
+            }
+        }
+        // Only the chosen branch is evaluated
+        Expr::Conditional(condition, then, otherwise) => {
+            let offset = condition.size();
+            if operand(condition, 0, scope)?.value.is_zero() {
+                operand(otherwise, offset + then.size(), scope)?
+            } else {
+                operand(then, offset, scope)?
+            }
+        }
+    })
+}
+
+/// Evaluate the arguments of a call with `evaluate`, given each one and the number of
+/// nodes of the previous ones.
+fn arguments<N, R>(
+    args: &[Expr<N>],
+    mut evaluate: impl FnMut(&Expr<N>, usize) -> Result<R, Located>,
+) -> Result<Vec<R>, Located> {
+    let mut offset = 0;
+    args.iter()
+        .map(|arg| {
+            let value = evaluate(arg, offset);
+            offset += arg.size();
+            value
+        })
+        .collect()
+}
+
+/// Whether `target` is made of units only, like `km/h`, `m**2` or `1/s`.
+fn is_unit<N: Numeric>(target: &Expr<N>) -> bool {
+    let is_integer = |expr: &Expr<N>| match expr {
+        Expr::UnaryOp(UnaryOpType::Negate, operand) => {
+            matches!(&**operand, Expr::Number(num) if num.is_integer())
+        }
+        Expr::Number(num) => num.is_integer(),
+        _ => false,
+    };
+
+    match target {
+        Expr::Variable(_) => true,
+        Expr::BinOp(left, BinOpType::Mul, right) => is_unit(left) && is_unit(right),
+        Expr::BinOp(left, BinOpType::Div, right) => {
+            let one = matches!(&**left, Expr::Number(num) if *num == N::from_literal("1"));
+            (one || is_unit(left)) && is_unit(right)
+        }
+        Expr::BinOp(left, BinOpType::Pow, right) => is_unit(left) && is_integer(right),
+        _ => false,
+    }
+}
+
+/// Apply the operator `op`, `checked` turns the special values of IEEE 754 into errors.
+pub(crate) fn apply_binary<N: Numeric>(
+    op: BinOpType,
+    left: N,
+    right: N,
+    checked: bool,
+) -> EvalResult<N> {
+    if checked {
+        match op {
+            BinOpType::Div | BinOpType::Mod | BinOpType::FloorDiv if right.is_zero() => {
+                return Err(EvalError::DivisionByZero)
+            }
+            BinOpType::Pow if left.is_zero() && right.is_negative() => {
+                return Err(EvalError::DivisionByZero)
+            }
+            // Complex numbers and NaN are not ordered
+            BinOpType::Lt | BinOpType::Le | BinOpType::Gt | BinOpType::Ge
+                if (left.clone() - right.clone()).to_f64().is_nan() =>
+            {
+                return Err(EvalError::Domain(op.symbol().into()))
+            }
+            _ => {}
+        }
+    }
+
+    let result = match op {
+        BinOpType::Add => left + right,
+        BinOpType::Sub => left - right,
+        BinOpType::Mul => left * right,
+        BinOpType::Div => left / right,
+        BinOpType::Mod => {
+            let quotient = floor(left.clone() / right.clone());
+            left - right * quotient
+        }
+        BinOpType::FloorDiv => floor(left / right),
+        BinOpType::Pow => left.pow(&right),
+        BinOpType::Eq => boolean(left == right),
+        BinOpType::Ne => boolean(left != right),
+        BinOpType::Lt => boolean(less(&left, &right, false)),
+        BinOpType::Le => boolean(less(&left, &right, true)),
+        BinOpType::Gt => boolean(less(&right, &left, false)),
+        BinOpType::Ge => boolean(less(&right, &left, true)),
+        BinOpType::And => boolean(!left.is_zero() && !right.is_zero()),
+        BinOpType::Or => boolean(!left.is_zero() || !right.is_zero()),
+    };
+
+    if checked {
+        check_result(result, op.symbol())
+    } else {
+        Ok(result)
+    }
+}
+
+/// `x + p%` as `x * (100 + p) / 100`, or with a `-` for a subtraction.
+pub(crate) fn relative_percentage<N: Numeric>(
+    left: &Expr<N>,
+    op: BinOpType,
+    percent: &Expr<N>,
+) -> Expr<N> {
+    let hundred = || Expr::Number(N::from_literal("100")).boxed();
+    let factor = Expr::BinOp(hundred(), op, percent.clone().boxed());
+    let scaled = Expr::BinOp(left.clone().boxed(), BinOpType::Mul, factor.boxed());
+    Expr::BinOp(scaled.boxed(), BinOpType::Div, hundred())
+}
+
+/// Largest integer less than or equal to `value`, computed on [f64] unless
+/// [Numeric#apply] can compute it directly.
+fn floor<N: Numeric>(value: N) -> N {
+    N::apply("floor", std::slice::from_ref(&value))
+        .unwrap_or_else(|| N::from_f64(value.to_f64().floor()))
+}
+
+/// Largest integer whose factorial is computed, it already has 35660 digits while an
+/// [f64] overflows from `171!`.
+const MAX_FACTORIAL: f64 = 10_000.;
+
+/// `n!`, computed exactly in `N` for the integers and with the gamma function at
+/// `n + 1` otherwise.
+pub(crate) fn factorial<N: Numeric>(n: N, checked: bool) -> EvalResult<N> {
+    if n.is_integer() && !n.is_negative() {
+        if n.to_f64() > MAX_FACTORIAL {
+            return if checked {
+                Err(EvalError::Overflow)
+            } else {
+                Ok(N::from_f64(f64::INFINITY))
+            };
+        }
+
+        let n = n.to_f64() as u32;
+        if let Some(result) = N::factorial(n) {
+            return Ok(result);
+        }
+
+        // The products are rounded, or overflow early for the floats
+        let mut result = N::from_literal("1");
+        for factor in 2..=n {
+            result = result * N::from_literal(&factor.to_string());
+            // Only the floats overflow, from 171!
+            if !result.is_finite() {
+                break;
+            }
+        }
+        return if checked {
+            check_result(result, "!")
+        } else {
+            Ok(result)
+        };
+    }
+
+    let gamma = builtin("gamma").unwrap();
+    let shifted = n + N::from_literal("1");
+    apply_builtin(gamma, &[shifted], checked).map_err(|e| match e {
+        EvalError::Domain(_) => EvalError::Domain("!".into()),
+        e => e,
+    })
+}
 
-pub trait Eval {
-    fn eval(&self) -> Number;
+/// The gamma function, with the Lanczos approximation and the reflection formula below
+/// 1/2.
+fn gamma(x: Number) -> Number {
+    const G: Number = 7.;
+    const COEFFICIENTS: [Number; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        return PI / ((PI * x).sin() * gamma(1. - x));
+    }
+
+    let x = x - 1.;
+    let t = x + G + 0.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (index, coefficient)| {
+            sum + coefficient / (x + index as Number + 1.)
+        });
+    // The power is split to stay finite where the result is
+    let half_power = t.powf((x + 0.5) / 2.);
+    (2. * PI).sqrt() * half_power * (-t).exp() * half_power * sum
+}
+
+/// 1 if `condition` holds, 0 otherwise.
+pub(crate) fn boolean<N: Numeric>(condition: bool) -> N {
+    N::from_literal(if condition { "1" } else { "0" })
+}
+
+/// Whether `left` is less than `right`, or equal to it if `or_equal`.
+fn less<N: Numeric>(left: &N, right: &N, or_equal: bool) -> bool {
+    (or_equal && left == right) || (left.clone() - right.clone()).is_negative()
+}
+
+pub(crate) fn check_finite<N: Numeric>(value: N, checked: bool) -> EvalResult<N> {
+    if checked && !value.is_finite() {
+        Err(EvalError::NonFinite)
+    } else {
+        Ok(value)
+    }
+}
+
+/// Turn a non-finite result of `operation` applied to finite operands into an error.
+fn check_result<N: Numeric>(result: N, operation: &str) -> EvalResult<N> {
+    if result.is_nan() {
+        Err(EvalError::Domain(operation.into()))
+    } else if !result.is_finite() {
+        Err(EvalError::Overflow)
+    } else {
+        Ok(result)
+    }
 }
 
-impl Eval for Expr {
-    fn eval(&self) -> f32 {
-        match self {
-            Expr::Number(num) => *num,
-            Expr::E => std::f32::consts::E,
-            Expr::Pi => std::f32::consts::PI,
-            Expr::UnaryOp(UnaryOpType::Negate, operand) => -operand.eval(),
-            Expr::UnaryOp(UnaryOpType::Noop, operand) => operand.eval(),
-            Expr::BinOp(left, BinOpType::Add, right) => left.eval() + right.eval(),
-            Expr::BinOp(left, BinOpType::Sub, right) => left.eval() - right.eval(),
-            Expr::BinOp(left, BinOpType::Mul, right) => left.eval() * right.eval(),
-            Expr::BinOp(left, BinOpType::Div, right) => left.eval() / right.eval(),
-            Expr::BinOp(left, BinOpType::Pow, right) => left.eval().powf(right.eval()),
+/// Number of arguments accepted by a function.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Arity {
+    pub min: usize,
+    /// No upper bound if `None`.
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub const fn exactly(count: usize) -> Self {
+        Self {
+            min: count,
+            max: Some(count),
+        }
+    }
+
+    pub const fn between(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
         }
     }
+
+    pub const fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// A function of the standard library.
+#[derive(Copy, Clone, Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    /// Called with a number of arguments accepted by `arity`.
+    pub function: fn(&[Number]) -> Number,
+    /// Whether the arguments are in the domain of the function, poles excluded.
+    pub domain: fn(&[Number]) -> bool,
+}
+
+macro_rules! unary {
+    ($name:literal, $method:ident) => {
+        unary!($name, $method, |_| true)
+    };
+    ($name:literal, $method:ident, $domain:expr) => {
+        Builtin {
+            name: $name,
+            arity: Arity::exactly(1),
+            function: |args| args[0].$method(),
+            domain: |args| ($domain)(args[0]),
+        }
+    };
+}
+
+fn any_domain(_: &[Number]) -> bool {
+    true
+}
+
+/// The standard math library.
+pub const BUILTINS: &[Builtin] = &[
+    // Trigonometry
+    unary!("sin", sin),
+    unary!("cos", cos),
+    unary!("tan", tan),
+    unary!("asin", asin, |x: Number| x.abs() <= 1.),
+    unary!("acos", acos, |x: Number| x.abs() <= 1.),
+    unary!("atan", atan),
+    Builtin {
+        name: "atan2",
+        arity: Arity::exactly(2),
+        function: |args| args[0].atan2(args[1]),
+        domain: any_domain,
+    },
+    // Hyperbolic
+    unary!("sinh", sinh),
+    unary!("cosh", cosh),
+    unary!("tanh", tanh),
+    unary!("asinh", asinh),
+    unary!("acosh", acosh, |x| x >= 1.),
+    unary!("atanh", atanh, |x: Number| x.abs() < 1.),
+    // Exponentials and logarithms
+    unary!("exp", exp),
+    unary!("sqrt", sqrt, |x| x >= 0.),
+    unary!("cbrt", cbrt),
+    Builtin {
+        // Poles at the non-positive integers
+        name: "gamma",
+        arity: Arity::exactly(1),
+        function: |args| gamma(args[0]),
+        domain: |args| args[0] > 0. || args[0].fract() != 0.,
+    },
+    unary!("ln", ln, |x| x > 0.),
+    unary!("log2", log2, |x| x > 0.),
+    unary!("log10", log10, |x| x > 0.),
+    Builtin {
+        // Base 10 unless specified
+        name: "log",
+        arity: Arity::between(1, 2),
+        function: |args| args[0].log(args.get(1).copied().unwrap_or(10.)),
+        domain: |args| match args {
+            [x] => *x > 0.,
+            [x, base] => *x > 0. && *base > 0. && *base != 1.,
+            _ => false,
+        },
+    },
+    // Rounding
+    unary!("floor", floor),
+    unary!("ceil", ceil),
+    unary!("round", round),
+    unary!("trunc", trunc),
+    // Misc
+    unary!("abs", abs),
+    Builtin {
+        name: "sign",
+        arity: Arity::exactly(1),
+        function: |args| {
+            if args[0] == 0. {
+                0.
+            } else {
+                args[0].signum()
+            }
+        },
+        domain: any_domain,
+    },
+    Builtin {
+        name: "min",
+        arity: Arity::at_least(1),
+        function: |args| args.iter().copied().fold(Number::INFINITY, Number::min),
+        domain: any_domain,
+    },
+    Builtin {
+        name: "max",
+        arity: Arity::at_least(1),
+        function: |args| args.iter().copied().fold(Number::NEG_INFINITY, Number::max),
+        domain: any_domain,
+    },
+];
+
+/// Find a function of the standard library by name.
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Find the function `name` and check that it accepts `count` arguments.
+pub(crate) fn resolve_builtin(name: &str, count: usize) -> EvalResult<&'static Builtin> {
+    let builtin = builtin(name).ok_or_else(|| EvalError::UnknownFunction(name.into()))?;
+
+    if builtin.arity.accepts(count) {
+        Ok(builtin)
+    } else {
+        Err(EvalError::WrongArity {
+            name: name.into(),
+            expected: builtin.arity,
+            got: count,
+        })
+    }
+}
+
+/// Apply `builtin` to a number of arguments it accepts, they are converted to [f64]
+/// unless [Numeric#apply] can compute it directly.
+pub(crate) fn apply_builtin<N: Numeric>(
+    builtin: &Builtin,
+    args: &[N],
+    checked: bool,
+) -> EvalResult<N> {
+    let result = match N::apply(builtin.name, args) {
+        Some(result) => result,
+        None => {
+            // Most functions have few arguments, avoid an allocation for them
+            let mut buffer = [0.; 4];
+            let heap;
+            let floats = if args.len() <= buffer.len() {
+                for (float, arg) in buffer.iter_mut().zip(args) {
+                    *float = arg.to_f64();
+                }
+                &buffer[..args.len()]
+            } else {
+                heap = args.iter().map(N::to_f64).collect::<Vec<_>>();
+                &heap[..]
+            };
+
+            if checked && !(builtin.domain)(floats) {
+                return Err(EvalError::Domain(builtin.name.into()));
+            }
+            N::from_f64((builtin.function)(floats))
+        }
+    };
+    if checked {
+        check_result(result, builtin.name)
+    } else {
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ast::{BinOpType, Expr, UnaryOpType};
-    use crate::eval::Eval;
+    use crate::complex::Complex;
+    use crate::errors::EvalError;
+    use crate::eval::{Arity, Environment, Eval};
+    use crate::parser::Parser;
+    use crate::token::{tokenize, tokenize_as};
 
     #[test]
     fn eval_atom() {
         let atom = Expr::Number(42.);
         assert_eq!(atom.eval(), 42.);
 
-        let atom = Expr::E;
-        assert_eq!(atom.eval(), std::f32::consts::E);
+        let atom: Expr = Expr::E;
+        assert_eq!(atom.eval(), std::f64::consts::E);
 
-        let atom = Expr::Pi;
-        assert_eq!(atom.eval(), std::f32::consts::PI);
+        let atom: Expr = Expr::Pi;
+        assert_eq!(atom.eval(), std::f64::consts::PI);
     }
 
     #[test]
@@ -65,5 +911,183 @@ mod tests {
         assert_eq!(div.eval(), 0.5);
         let pow = Expr::BinOp(one.clone().boxed(), BinOpType::Pow, two.clone().boxed());
         assert_eq!(pow.eval(), 1.);
+        let rem = Expr::BinOp(one.clone().boxed(), BinOpType::Mod, two.clone().boxed());
+        assert_eq!(rem.eval(), 1.);
+        let floor_div = Expr::BinOp(
+            one.clone().boxed(),
+            BinOpType::FloorDiv,
+            two.clone().boxed(),
+        );
+        assert_eq!(floor_div.eval(), 0.);
+    }
+
+    #[test]
+    fn eval_logic() {
+        let eval = |source: &str| {
+            Parser::new(tokenize(source).unwrap())
+                .parse()
+                .unwrap()
+                .try_eval()
+        };
+
+        assert_eq!(eval("1 < 2"), Ok(1.));
+        assert_eq!(eval("2 <= 2 and 3 >= 4"), Ok(0.));
+        assert_eq!(eval("1 == 1.0 != 0"), Ok(1.));
+        assert_eq!(eval("not 2 or 0 > -1"), Ok(1.));
+        assert_eq!(eval("1 km > 500 m"), Ok(1.));
+        assert_eq!(eval("150 > 100 ? 150*0.9 : 150"), Ok(135.));
+        assert_eq!(eval("0 ? 1 : 0 ? 2 : 3"), Ok(3.));
+
+        // Only the operands deciding the result are evaluated
+        assert_eq!(eval("0 and 1/0"), Ok(0.));
+        assert_eq!(eval("2 or 1/0"), Ok(1.));
+        assert_eq!(eval("1 ? 2 : sqrt(-1)"), Ok(2.));
+        assert_eq!(eval("1 and 1/0"), Err(EvalError::DivisionByZero));
+
+        assert!(matches!(
+            eval("1 km < 1 s"),
+            Err(EvalError::IncompatibleUnits { .. })
+        ));
+
+        // Complex numbers can be compared for equality only
+        let eval_complex = |source: &str| {
+            Parser::new(tokenize_as::<Complex>(source).unwrap())
+                .parse()
+                .unwrap()
+                .try_eval()
+        };
+        assert_eq!(eval_complex("i == i"), Ok(Complex::new(1., 0.)));
+        assert_eq!(eval_complex("i > 0"), Err(EvalError::Domain(">".into())));
+    }
+
+    #[test]
+    fn eval_postfix() {
+        let eval = |source: &str| {
+            Parser::new(tokenize(source).unwrap())
+                .parse()
+                .unwrap()
+                .try_eval()
+        };
+
+        // The sign of the divisor, like the floor division
+        assert_eq!(eval("7 % 3"), Ok(1.));
+        assert_eq!(eval("-7 % 3"), Ok(2.));
+        assert_eq!(eval("7 % (-3)"), Ok(-2.));
+        assert_eq!(eval("7 % -3"), Ok(-2.));
+        assert_eq!(eval("-7 %+3"), Ok(2.));
+        assert_eq!(eval("-7 // 2"), Ok(-4.));
+        assert_eq!(eval("7.5 // 2 * 2 + 7.5 % 2"), Ok(7.5));
+        assert_eq!(eval("1 % 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("1 // 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("8 / 4 / 2"), Ok(1.));
+        assert_eq!(eval("8 / 4 // 2"), Ok(1.));
+        assert_eq!(eval("9 // 2 / 2"), Ok(2.));
+        assert_eq!(eval("12 / 4 % 2"), Ok(1.));
+        assert_eq!(eval("7 % 4 / 2"), Ok(1.5));
+
+        assert_eq!(eval("5!"), Ok(120.));
+        assert_eq!(eval("0! + 3!!"), Ok(721.));
+        assert_eq!(eval("-3!"), Ok(-6.));
+        assert!((eval("0.5!").unwrap() - std::f64::consts::PI.sqrt() / 2.).abs() < 1e-14);
+        assert!((eval("gamma(-1.5)").unwrap() - 2.363_271_801_207_355).abs() < 1e-14);
+        assert_eq!(eval("(-2)!"), Err(EvalError::Domain("!".into())));
+        assert_eq!(eval("171!"), Err(EvalError::Overflow));
+        assert_eq!(eval("1e9!"), Err(EvalError::Overflow));
+
+        assert_eq!(eval("200 * 15%"), Ok(30.));
+        assert_eq!(eval("50 + 10%"), Ok(55.));
+        assert_eq!(eval("50 - 10% - 10%"), Ok(40.5));
+        assert_eq!(eval("10% + 50"), Ok(50.1));
+        assert_eq!(eval("1 km + 10% in m"), Ok(1100.));
+    }
+
+    #[test]
+    fn eval_variables() {
+        let sum = Expr::BinOp(
+            Expr::Variable("x".into()).boxed(),
+            BinOpType::Add,
+            Expr::Variable("y".into()).boxed(),
+        );
+
+        let mut env = vec![("y", 2.)].into_iter().collect::<Environment>();
+        assert_eq!(env.set("x", 1.), None);
+        assert_eq!(sum.eval_in(&env), Ok(3.));
+        assert_eq!(env.variables(), vec![("x", &1.), ("y", &2.)]);
+
+        let env = vec![("x", 1.)].into_iter().collect::<Environment>();
+        assert_eq!(
+            sum.eval_in(&env),
+            Err(EvalError::UnboundVariable("y".into()))
+        );
+        assert!(sum.eval().is_nan());
+    }
+
+    #[test]
+    fn eval_calls() {
+        let call = |name: &str, args: Vec<f64>| {
+            Expr::Call(name.into(), args.into_iter().map(Expr::Number).collect())
+                .eval_in(&Environment::new())
+        };
+
+        assert_eq!(call("sqrt", vec![4.]), Ok(2.));
+        assert_eq!(call("log", vec![100.]), Ok(2.));
+        assert_eq!(call("log", vec![8., 2.]), Ok(3.));
+        assert_eq!(call("max", vec![1., 3., 2.]), Ok(3.));
+        assert_eq!(call("min", vec![1., 3., 2.]), Ok(1.));
+        assert_eq!(call("sign", vec![-3.]), Ok(-1.));
+        assert_eq!(call("sign", vec![0.]), Ok(0.));
+        assert_eq!(call("abs", vec![-3.]), Ok(3.));
+
+        assert_eq!(
+            call("nope", vec![1.]),
+            Err(EvalError::UnknownFunction("nope".into()))
+        );
+        assert_eq!(
+            call("sin", vec![1., 2.]),
+            Err(EvalError::WrongArity {
+                name: "sin".into(),
+                expected: Arity::exactly(1),
+                got: 2
+            })
+        );
+        assert_eq!(
+            call("max", vec![]).unwrap_err().to_string(),
+            "Function 'max' expects at least 1 arguments, got 0"
+        );
+    }
+
+    #[test]
+    fn eval_errors() {
+        let eval = |source: &str| {
+            Parser::new(tokenize(source).unwrap())
+                .parse()
+                .unwrap()
+                .try_eval()
+        };
+
+        assert_eq!(eval("1/0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("0**-1"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("(-1)**0.5"), Err(EvalError::Domain("**".into())));
+        assert_eq!(eval("sqrt(-4)"), Err(EvalError::Domain("sqrt".into())));
+        assert_eq!(eval("ln(0)"), Err(EvalError::Domain("ln".into())));
+        assert_eq!(eval("log(8, 1)"), Err(EvalError::Domain("log".into())));
+        assert_eq!(eval("10**400"), Err(EvalError::Overflow));
+        assert_eq!(eval("exp(1000)"), Err(EvalError::Overflow));
+        assert_eq!(eval("(-8)**2"), Ok(64.));
+        assert_eq!(eval("2i"), Err(EvalError::NotComplex));
+
+        let env = vec![("x", f64::NAN)].into_iter().collect();
+        assert_eq!(
+            Expr::Variable("x".into()).eval_in(&env),
+            Err(EvalError::NonFinite)
+        );
+
+        // The unchecked evaluation follows IEEE 754
+        let div = Expr::BinOp(
+            Expr::Number(1.).boxed(),
+            BinOpType::Div,
+            Expr::Number(0.).boxed(),
+        );
+        assert_eq!(div.eval(), f64::INFINITY);
     }
 }
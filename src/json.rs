@@ -0,0 +1,109 @@
+//! # JSON module
+//! Machine-readable output of the binary, a JSON object per expression.
+
+use serde_json::{json, Value};
+
+use rust_calculator::errors::Error;
+use rust_calculator::numeric::Numeric;
+use rust_calculator::units::Quantity;
+
+/// Describe the outcome of evaluating `input`, read at `line` in batch mode.
+///
+/// The `result` is the closest [f64], or `null` if there is none, in `unit`, `null` if
+/// dimensionless, and `text` is the result as displayed by the text output. The `span`
+/// of an error is in bytes, and `null` for the evaluation errors that aren't located:
+/// ```json
+/// {"input": "1+", "result": null, "unit": null, "text": null, "error": {"kind":
+///  "not_enough_operands", "message": "Not enough operands in the expression !",
+///  "span": {"start": 1, "end": 2}}}
+/// ```
+pub fn report<N: Numeric>(
+    input: &str,
+    line: Option<usize>,
+    outcome: &Result<Quantity<N>, Error>,
+) -> Value {
+    let mut report = match outcome {
+        Ok(result) => json!({
+            "input": input,
+            "result": result.value.to_f64(),
+            "unit": result.unit_name(),
+            "text": result.to_string(),
+            "error": null,
+        }),
+        Err(e) => json!({
+            "input": input,
+            "result": null,
+            "unit": null,
+            "text": null,
+            "error": {
+                "kind": e.kind(),
+                "message": e.to_string(),
+                "span": e.span().map(|span| json!({ "start": span.start, "end": span.end })),
+            },
+        }),
+    };
+
+    if let Some(line) = line {
+        report["line"] = line.into();
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use rust_calculator::errors::{Error, EvalError};
+    use rust_calculator::units::Quantity;
+
+    use crate::evaluate;
+    use crate::json::report;
+
+    #[test]
+    fn report_outcomes() {
+        assert_eq!(
+            report(" 1+2", None, &evaluate::<f64>(" 1+2")),
+            json!({ "input": " 1+2", "result": 3.0, "unit": null, "text": "3", "error": null })
+        );
+        assert_eq!(
+            report("1 km in m", None, &evaluate::<f64>("1 km in m")),
+            json!({
+                "input": "1 km in m",
+                "result": 1000.0,
+                "unit": "m",
+                "text": "1000 m",
+                "error": null,
+            })
+        );
+        assert_eq!(
+            report("(1))", Some(3), &evaluate::<f64>("(1))")),
+            json!({
+                "input": "(1))",
+                "line": 3,
+                "result": null,
+                "unit": null,
+                "text": null,
+                "error": {
+                    "kind": "mismatched_parenthesis",
+                    "message": "Mismatched parenthesis !",
+                    "span": { "start": 3, "end": 4 },
+                },
+            })
+        );
+
+        assert_eq!(
+            report("2 + 1/0", None, &evaluate::<f64>("2 + 1/0"))["error"],
+            json!({
+                "kind": "division_by_zero",
+                "message": "Division by zero !",
+                "span": { "start": 5, "end": 6 },
+            })
+        );
+        let outcome: Result<Quantity<f64>, Error> = Err(EvalError::DivisionByZero.into());
+        assert_eq!(
+            report("1/0", None, &outcome)["error"],
+            json!({ "kind": "division_by_zero", "message": "Division by zero !", "span": null })
+        );
+    }
+}
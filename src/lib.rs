@@ -1,40 +1,276 @@
 //! # Simple expression parser
-//! Grammar used:
+//! Grammar used, kept in sync with `grammar.bnf`:
 //! ```bnf
-//! <expr>  ::= <add>
-//! <add>   ::= <mul> [ <addOp> <add> ]
-//! <addOp> ::= '+' | '-'
-//! <mul>   ::= <atom> [ <mulOp> <mul> ]
-//! <mulOp> ::= '*' / '/'
-//! <atom> ::= <literal> | '(' <add> ')'
-//! <literal> ::= <digit> | 'e' | 'pi'
-//! <digit> ::= '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' | <digit>
+//! <grammar> ::= <program> | <<eof>>
+//! <program> ::= [ <statement> ] [ ( <separator> [ <statement> ] ) + ]
+//! <separator> ::= ";" | "\n"
+//! <statement> ::= <ident> "=" <convert> | <signature> "=" <convert> | <convert>
+//! <signature> ::= <ident> "(" [ <ident> [ ( "," <ident> ) + ] ] ")"
+//! <convert> ::= <cond> [ ( ( "in" | "to" ) <cond> ) + ]
+//! <cond>    ::= <or> [ "?" <cond> ":" <cond> ]
+//! <or>      ::= <and> [ ( "or" <and> ) + ]
+//! <and>     ::= <not> [ ( "and" <not> ) + ]
+//! <not>     ::= "not" <not> | <compare>
+//! <compare> ::= <add> [ ( ( "==" | "!=" | "<" | "<=" | ">" | ">=" ) <add> ) + ]
+//! <add>     ::= <mul> [ ( ( "+" | "-" ) <mul> ) + ]
+//! <mul>     ::= <pow> [ ( ( "*" | "/" | "%" | "//" ) <pow> | <pow> <pow> ) + ]
+//! <pow>     ::= <unary> [ ( "**" <unary> ) + ]
+//! <unary>   ::= <postfix> | "+" <postfix> | "-" <postfix>
+//! <postfix> ::= <atom> [ ( "!" | "%" ) + ]
+//! <atom>    ::= <literal> | "(" <convert> ")" | <call>
+//! <call>    ::= <ident> "(" [ <convert> [ ( "," <convert> ) + ] ] ")"
+//! <literal> ::= <number> [ <unit> ] | "e" | "pi" | "i" | <ident>
+//! <unit>    ::= <unit_power> [ ( ( "*" | "/" ) <unit_power> ) + ]
+//! <unit_power> ::= <ident> [ "**" [ "-" ] <digits> ]
+//! <number>  ::= <decimal> [ <exponent> ] | "0x" <hex> | "0o" <oct> | "0b" <bin>
+//! <decimal> ::= <digits> [ "." [ <digits> ] ] | "." <digits>
+//! <exponent> ::= ( "e" | "E" ) [ "+" | "-" ] <digits>
+//! <digits>  ::= <digit> [ ( [ "_" ] <digit> ) + ]
+//! <digit>   ::= "0" .. "9"
+//! <hex>     ::= <hex_digit> [ ( [ "_" ] <hex_digit> ) + ]
+//! <hex_digit> ::= <digit> | "a" .. "f" | "A" .. "F"
+//! <oct>     ::= <oct_digit> [ ( [ "_" ] <oct_digit> ) + ]
+//! <oct_digit> ::= "0" .. "7"
+//! <bin>     ::= <bin_digit> [ ( [ "_" ] <bin_digit> ) + ]
+//! <bin_digit> ::= "0" | "1"
+//! <ident>   ::= <letter> [ ( <letter> | <digit> ) + ]
+//! <letter>  ::= "a" .. "z" | "A" .. "Z" | "_"
 //! ```
 
 pub mod ast;
+pub mod bytecode;
+pub mod columns;
+pub mod complex;
+pub mod decimal;
+pub mod derivative;
+pub mod diagnostic;
 pub mod eval;
+pub mod numeric;
 pub mod parser;
+pub mod program;
+pub mod rational;
+pub mod simplify;
+pub mod span;
 pub mod token;
+pub mod units;
 
 pub mod errors {
-    use crate::token::Token;
+    use crate::eval::Arity;
+    use crate::span::Span;
+    use crate::units::Dimension;
     use thiserror::Error;
 
     pub type Result<T> = std::result::Result<T, ParserError>;
+    pub type EvalResult<T> = std::result::Result<T, EvalError>;
+
+    /// Any error that can happen between reading the input and evaluating it.
+    #[derive(Error, Debug)]
+    pub enum Error {
+        #[error(transparent)]
+        Parser(#[from] ParserError),
+        /// An evaluation error, with the operator or call raising it if it is known.
+        #[error("{0}")]
+        Eval(EvalError, Option<Span>),
+    }
+
+    impl From<EvalError> for Error {
+        fn from(error: EvalError) -> Self {
+            Self::Eval(error, None)
+        }
+    }
+
+    impl Error {
+        /// Stable name of the variant, for machine-readable output.
+        pub fn kind(&self) -> &'static str {
+            match self {
+                Self::Parser(e) => e.kind(),
+                Self::Eval(e, _) => e.kind(),
+            }
+        }
+
+        /// Location in the source, if it is known.
+        pub fn span(&self) -> Option<Span> {
+            match self {
+                Self::Parser(e) => Some(e.span()),
+                Self::Eval(_, span) => *span,
+            }
+        }
+    }
 
     #[derive(Error, Debug)]
     pub enum ParserError {
-        #[error("Tokenization error: {0}")]
-        Tokenize(String),
+        #[error("Unexpected character '{0}'")]
+        UnexpectedChar(char, Span),
+        #[error("A single dot isn't a valid number !")]
+        LoneDot(Span),
+        #[error("A '_' in a number can only separate two digits !")]
+        MisplacedUnderscore(Span),
+        #[error("Unexpected comma !")]
+        UnexpectedComma(Span),
         #[error("Mismatched parenthesis !")]
-        MismatchedParenthesis,
+        MismatchedParenthesis(char, Span),
         #[error("Too much operands in the expression !")]
-        TooMuchOperands,
+        TooMuchOperands(Span),
         #[error("Not enough operands in the expression !")]
-        NotEnoughOperands,
+        NotEnoughOperands(Span),
         #[error("Unexpected operator: {0}")]
-        UnexpectedOperator(Token),
+        UnexpectedOperator(String, Span),
         #[error("Unsupported operator: {0}")]
-        UnsupportedOperator(Token),
+        UnsupportedOperator(String, Span),
+        #[error("Expected a single expression !")]
+        NotAnExpression(Span),
+        #[error("Only a variable or a function like f(x, y) can be assigned !")]
+        InvalidAssignment(Span),
+        #[error("Mismatched conditional !")]
+        MismatchedConditional(Span),
+    }
+
+    impl ParserError {
+        /// Location in the source of the input that caused the error.
+        pub fn span(&self) -> Span {
+            match self {
+                Self::UnexpectedChar(_, span)
+                | Self::LoneDot(span)
+                | Self::MisplacedUnderscore(span)
+                | Self::UnexpectedComma(span)
+                | Self::MismatchedParenthesis(_, span)
+                | Self::TooMuchOperands(span)
+                | Self::NotEnoughOperands(span)
+                | Self::UnexpectedOperator(_, span)
+                | Self::UnsupportedOperator(_, span)
+                | Self::NotAnExpression(span)
+                | Self::InvalidAssignment(span)
+                | Self::MismatchedConditional(span) => *span,
+            }
+        }
+
+        /// Stable name of the variant, for machine-readable output.
+        pub fn kind(&self) -> &'static str {
+            match self {
+                Self::UnexpectedChar(..) => "unexpected_char",
+                Self::LoneDot(_) => "lone_dot",
+                Self::MisplacedUnderscore(_) => "misplaced_underscore",
+                Self::UnexpectedComma(_) => "unexpected_comma",
+                Self::MismatchedParenthesis(..) => "mismatched_parenthesis",
+                Self::TooMuchOperands(_) => "too_much_operands",
+                Self::NotEnoughOperands(_) => "not_enough_operands",
+                Self::UnexpectedOperator(..) => "unexpected_operator",
+                Self::UnsupportedOperator(..) => "unsupported_operator",
+                Self::NotAnExpression(_) => "not_an_expression",
+                Self::InvalidAssignment(_) => "invalid_assignment",
+                Self::MismatchedConditional(_) => "mismatched_conditional",
+            }
+        }
+
+        /// Short description of what is wrong at [ParserError#span].
+        pub fn label(&self) -> String {
+            match self {
+                Self::UnexpectedChar(..) => "not part of any token".into(),
+                Self::LoneDot(_) => "expected digits around this dot".into(),
+                Self::MisplacedUnderscore(_) => "not between two digits".into(),
+                Self::UnexpectedComma(_) => {
+                    "commas can only separate the arguments of a function call".into()
+                }
+                Self::MismatchedParenthesis(')', _) => "this ')' has no matching '('".into(),
+                Self::MismatchedParenthesis(..) => "this '(' is never closed".into(),
+                Self::TooMuchOperands(_) => "this operand is not used by any operator".into(),
+                Self::NotEnoughOperands(_) => "this operator is missing an operand".into(),
+                Self::UnexpectedOperator(..) => "unexpected here".into(),
+                Self::UnsupportedOperator(..) => "not supported".into(),
+                Self::NotAnExpression(_) => "statements are only allowed in programs".into(),
+                Self::InvalidAssignment(_) => {
+                    "not a variable or a function of distinct parameters".into()
+                }
+                Self::MismatchedConditional(_) => "expected `condition ? then : else`".into(),
+            }
+        }
+    }
+
+    #[derive(Error, Debug, PartialEq)]
+    pub enum EvalError {
+        #[error("Unbound variable: {0}")]
+        UnboundVariable(String),
+        #[error("Unknown function: {0}")]
+        UnknownFunction(String),
+        #[error("Function '{name}' expects {expected} arguments, got {got}")]
+        WrongArity {
+            name: String,
+            expected: Arity,
+            got: usize,
+        },
+        #[error("Division by zero !")]
+        DivisionByZero,
+        #[error("Argument out of the domain of '{0}'")]
+        Domain(String),
+        #[error("Overflow, the result is too large !")]
+        Overflow,
+        #[error("Non-finite value in the expression !")]
+        NonFinite,
+        #[error("The imaginary unit needs complex numbers !")]
+        NotComplex,
+        #[error("Incompatible units, expected {expected} but got {got}")]
+        IncompatibleUnits { expected: Dimension, got: Dimension },
+        #[error("Unknown unit: {0}")]
+        UnknownUnit(String),
+        #[error("Not a unit: {0}")]
+        NotAUnit(String),
+        #[error("Units aren't supported here: {0}")]
+        UnsupportedUnit(String),
+        #[error("Too many nested calls, the limit is {0} !")]
+        RecursionLimit(usize),
+    }
+
+    #[derive(Error, Debug, PartialEq)]
+    pub enum DiffError {
+        #[error("Unknown function: {0}")]
+        UnknownFunction(String),
+        #[error("Function '{0}' can't be differentiated")]
+        NotDifferentiable(String),
+        #[error("Function '{name}' expects {expected} arguments, got {got}")]
+        WrongArity {
+            name: String,
+            expected: Arity,
+            got: usize,
+        },
+    }
+
+    impl EvalError {
+        /// Stable name of the variant, for machine-readable output.
+        pub fn kind(&self) -> &'static str {
+            match self {
+                Self::UnboundVariable(_) => "unbound_variable",
+                Self::UnknownFunction(_) => "unknown_function",
+                Self::WrongArity { .. } => "wrong_arity",
+                Self::DivisionByZero => "division_by_zero",
+                Self::Domain(_) => "domain",
+                Self::Overflow => "overflow",
+                Self::NonFinite => "non_finite",
+                Self::NotComplex => "not_complex",
+                Self::IncompatibleUnits { .. } => "incompatible_units",
+                Self::UnknownUnit(_) => "unknown_unit",
+                Self::NotAUnit(_) => "not_a_unit",
+                Self::UnsupportedUnit(_) => "unsupported_unit",
+                Self::RecursionLimit(_) => "recursion_limit",
+            }
+        }
+
+        /// Short description of what is wrong at the operator or call raising the error.
+        pub fn label(&self) -> &'static str {
+            match self {
+                Self::UnboundVariable(_) => "not bound to any value",
+                Self::UnknownFunction(_) => "neither a builtin nor a defined function",
+                Self::WrongArity { .. } => "called with the wrong number of arguments",
+                Self::DivisionByZero => "the divisor is zero",
+                Self::Domain(_) => "an argument is out of its domain",
+                Self::Overflow => "the result is too large",
+                Self::NonFinite => "not a finite number",
+                Self::NotComplex => "only with complex numbers",
+                Self::IncompatibleUnits { .. } => "the units of the operands don't match",
+                Self::UnknownUnit(_) => "not a known unit",
+                Self::NotAUnit(_) => "the target of the conversion isn't a unit",
+                Self::UnsupportedUnit(_) => "units aren't supported here",
+                Self::RecursionLimit(_) => "this call nests too many others",
+            }
+        }
     }
 }
@@ -1,21 +1,268 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use rust_calculator::diagnostic::Diagnostic;
 use rust_calculator::parser::Parser;
-use rust_calculator::token::tokenize;
+use rust_calculator::token::Lexer;
+
+use rust_calculator::ast::Number;
+use rust_calculator::complex::Complex;
+use rust_calculator::decimal::{Decimal, DecimalContext};
+use rust_calculator::errors::{Error, ParserError};
+use rust_calculator::eval::Environment;
+use rust_calculator::numeric::Numeric;
+use rust_calculator::rational::Rational;
+use rust_calculator::simplify::simplify;
+use rust_calculator::span::Span;
+use rust_calculator::units::Quantity;
+
+mod batch;
+mod json;
+mod repl;
+
+use batch::Summary;
+
+/// Command line options, every other argument is part of the expression.
+#[derive(Debug, Default)]
+struct Options {
+    /// `--rational`: compute with exact fractions.
+    rational: bool,
+    /// `--complex`: compute with complex numbers.
+    complex: bool,
+    /// `--decimal`, `--precision=N` or `--rounding=MODE`: compute with rounded decimals.
+    decimal: Option<DecimalContext>,
+    /// `--batch` or `--batch=FILE`: evaluate each line of the standard input or of `FILE`.
+    batch: Option<Option<String>>,
+    /// `--json`: print a JSON object per expression instead of text.
+    json: bool,
+    /// `--simplify`: print the simplified expression instead of its value.
+    simplify: bool,
+    expr: String,
+}
 
-use rust_calculator::errors::Result;
-use rust_calculator::eval::Eval;
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        for arg in args {
+            match arg.as_str() {
+                "--rational" => options.rational = true,
+                "--complex" => options.complex = true,
+                "--json" => options.json = true,
+                "--simplify" => options.simplify = true,
+                "--batch" => options.batch = Some(None),
+                option if option.starts_with("--batch=") => {
+                    options.batch = Some(Some(option["--batch=".len()..].into()))
+                }
+                "--decimal" => {
+                    options.decimal.get_or_insert_with(DecimalContext::default);
+                }
+                option if option.starts_with("--precision=") => {
+                    let precision = option["--precision=".len()..]
+                        .parse()
+                        .ok()
+                        .filter(|&precision| precision > 0)
+                        .ok_or_else(|| format!("Invalid precision in '{}'", option))?;
+                    options
+                        .decimal
+                        .get_or_insert_with(DecimalContext::default)
+                        .precision = precision;
+                }
+                option if option.starts_with("--rounding=") => {
+                    let rounding = option["--rounding=".len()..].parse()?;
+                    options
+                        .decimal
+                        .get_or_insert_with(DecimalContext::default)
+                        .rounding = rounding;
+                }
+                option if option.starts_with("--") => {
+                    return Err(format!("Unknown option '{}'", option))
+                }
+                _ => {
+                    if !options.expr.is_empty() {
+                        options.expr.push(' ');
+                    }
+                    options.expr.push_str(&arg)
+                }
+            }
+        }
+
+        if options.batch.is_some() && !options.expr.is_empty() {
+            return Err("An expression can't be given in batch mode".into());
+        }
+        if options.simplify && (options.batch.is_some() || options.json) {
+            return Err("--simplify can't be combined with --batch or --json".into());
+        }
+        if options.simplify && options.expr.is_empty() {
+            return Err("--simplify needs an expression".into());
+        }
+
+        Ok(options)
+    }
+}
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("{}", e);
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    });
+
+    if let Some(file) = &options.batch {
+        match run_batch(&options, file.as_deref()) {
+            Ok(summary) if summary.failed > 0 => {
+                eprintln!(
+                    "error: {} of {} expressions failed",
+                    summary.failed, summary.evaluated
+                );
+                std::process::exit(1);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(2);
+            }
+        }
+    } else if options.expr.is_empty() {
+        if let Err(e) = run_repl(&options) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    } else if options.simplify {
+        if !run_simplify(&options) {
+            std::process::exit(1);
+        }
+    } else if !run(&options) {
+        std::process::exit(1);
+    }
+}
+
+/// Evaluate the expression and print its outcome, returns whether it succeeded.
+fn run(options: &Options) -> bool {
+    if let Some(context) = &options.decimal {
+        context.scope(|| {
+            let outcome = evaluate::<Decimal>(&options.expr).map(|result| Quantity {
+                value: result.value.round(context),
+                ..result
+            });
+            print_outcome(options, outcome)
+        })
+    } else if options.complex {
+        print_outcome(options, evaluate::<Complex>(&options.expr))
+    } else if options.rational {
+        print_outcome(options, evaluate::<Rational>(&options.expr))
+    } else {
+        print_outcome(options, evaluate::<Number>(&options.expr))
+    }
+}
+
+fn print_outcome<N: Numeric>(options: &Options, outcome: Result<Quantity<N>, Error>) -> bool {
+    if options.json {
+        println!("{}", json::report(&options.expr, None, &outcome));
+    } else {
+        match &outcome {
+            Ok(result) => println!("{}", result),
+            Err(e) => eprint!("{}", Diagnostic::from_error(&options.expr, e)),
+        }
     }
+
+    outcome.is_ok()
 }
 
-fn run() -> Result<()> {
-    let raw_expr = std::env::args().skip(1).collect::<String>();
-    let tokens = tokenize(raw_expr)?;
-    let parser = Parser::new(tokens);
-    let expr = parser.parse()?;
+/// Print the simplified expression, returns whether it could be parsed.
+fn run_simplify(options: &Options) -> bool {
+    if let Some(context) = &options.decimal {
+        context.scope(|| print_simplified::<Decimal>(&options.expr))
+    } else if options.complex {
+        print_simplified::<Complex>(&options.expr)
+    } else if options.rational {
+        print_simplified::<Rational>(&options.expr)
+    } else {
+        print_simplified::<Number>(&options.expr)
+    }
+}
 
-    println!("{}", expr.eval());
-    Ok(())
+fn print_simplified<N: Numeric>(raw_expr: &str) -> bool {
+    match Parser::from_tokens(Lexer::<N>::new(raw_expr)).parse() {
+        Ok(expr) => {
+            println!("{}", simplify(&expr));
+            true
+        }
+        Err(e) => {
+            eprint!("{}", Diagnostic::from_parser_error(raw_expr, &e));
+            false
+        }
+    }
+}
+
+fn run_batch(options: &Options, file: Option<&str>) -> io::Result<Summary> {
+    let input: Box<dyn BufRead> = match file {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let output = io::stdout().lock();
+    let json = options.json;
+
+    if let Some(context) = &options.decimal {
+        context.scope(|| batch::run::<Decimal>(input, output, json))
+    } else if options.complex {
+        batch::run::<Complex>(input, output, json)
+    } else if options.rational {
+        batch::run::<Rational>(input, output, json)
+    } else {
+        batch::run::<Number>(input, output, json)
+    }
+}
+
+fn run_repl(options: &Options) -> rustyline::Result<()> {
+    if let Some(context) = &options.decimal {
+        context.scope(repl::run::<Decimal>)
+    } else if options.complex {
+        repl::run::<Complex>()
+    } else if options.rational {
+        repl::run::<Rational>()
+    } else {
+        repl::run::<Number>()
+    }
+}
+
+/// Evaluate the program `raw_expr`, its last statement must have a value.
+fn evaluate<N: Numeric>(raw_expr: &str) -> Result<Quantity<N>, Error> {
+    // A program without any value is missing it like an empty expression
+    let end = Span::new(raw_expr.len(), raw_expr.len());
+    evaluate_in(raw_expr, &mut Environment::new())?
+        .ok_or_else(|| ParserError::NotEnoughOperands(end).into())
+}
+
+/// Evaluate the program `raw_expr`, returns the value of its last statement.
+fn evaluate_in<N: Numeric>(
+    raw_expr: &str,
+    env: &mut Environment<N>,
+) -> Result<Option<Quantity<N>>, Error> {
+    let program = Parser::from_tokens(Lexer::<N>::new(raw_expr)).parse_program()?;
+
+    program.eval_located_in(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{evaluate, Options};
+
+    fn parse(args: &[&str]) -> Options {
+        Options::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn join_arguments() {
+        let options = parse(&["3", "km", "in", "m"]);
+        assert_eq!(options.expr, "3 km in m");
+        assert_eq!(
+            evaluate::<f64>(&options.expr).unwrap().to_string(),
+            "3000 m"
+        );
+
+        let options = parse(&["--rational", "1", "and", "0"]);
+        assert!(options.rational);
+        assert_eq!(options.expr, "1 and 0");
+        assert_eq!(evaluate::<f64>(&options.expr).unwrap().to_string(), "0");
+    }
 }
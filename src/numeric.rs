@@ -0,0 +1,158 @@
+//! # Numeric module
+//! Contains the [Numeric] trait abstracting the type of the numbers manipulated by the
+//! tokenizer, the AST and the evaluator.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::eval::Eval;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize_as;
+//! let expr = Parser::new(tokenize_as::<f32>("1/3").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! assert_eq!(expr.eval(), 1f32 / 3.);
+//! # }
+//! ```
+
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A number type usable in an [Expr](crate::ast::Expr).
+pub trait Numeric:
+    Clone
+    + PartialEq
+    + Debug
+    + Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    /// Read a decimal literal of the `<number>` grammar without separators, digits with
+    /// at most one dot and an optional exponent like `2.5e-3`.
+    fn from_literal(literal: &str) -> Self;
+
+    /// Closest value to `value`, used for the results computed on [f64].
+    fn from_f64(value: f64) -> Self;
+
+    fn to_f64(&self) -> f64;
+
+    fn e() -> Self;
+
+    fn pi() -> Self;
+
+    /// The imaginary unit, or `None` if the type only holds real numbers.
+    fn i() -> Option<Self> {
+        None
+    }
+
+    fn pow(&self, exponent: &Self) -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    fn is_negative(&self) -> bool;
+
+    fn is_integer(&self) -> bool;
+
+    /// Whether the value is neither infinite nor NaN.
+    fn is_finite(&self) -> bool {
+        true
+    }
+
+    fn is_nan(&self) -> bool {
+        false
+    }
+
+    /// Apply the builtin function `name` to arguments it accepts, or `None` to
+    /// compute it on [f64] instead.
+    fn apply(_name: &str, _args: &[Self]) -> Option<Self> {
+        None
+    }
+
+    /// `n!`, or `None` to multiply the factors in `Self` instead.
+    fn factorial(_n: u32) -> Option<Self> {
+        None
+    }
+}
+
+macro_rules! impl_numeric_float {
+    ($float:ident) => {
+        impl Numeric for $float {
+            fn from_literal(literal: &str) -> Self {
+                // The tokenizer only produces valid literals
+                literal.parse().unwrap()
+            }
+
+            fn from_f64(value: f64) -> Self {
+                value as $float
+            }
+
+            fn to_f64(&self) -> f64 {
+                *self as f64
+            }
+
+            fn e() -> Self {
+                std::$float::consts::E
+            }
+
+            fn pi() -> Self {
+                std::$float::consts::PI
+            }
+
+            fn pow(&self, exponent: &Self) -> Self {
+                self.powf(*exponent)
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0.
+            }
+
+            fn is_negative(&self) -> bool {
+                *self < 0.
+            }
+
+            fn is_integer(&self) -> bool {
+                self.fract() == 0.
+            }
+
+            fn is_finite(&self) -> bool {
+                $float::is_finite(*self)
+            }
+
+            fn is_nan(&self) -> bool {
+                $float::is_nan(*self)
+            }
+        }
+    };
+}
+
+impl_numeric_float!(f32);
+impl_numeric_float!(f64);
+
+/// Split a decimal literal in its digits and the power of ten they are multiplied by,
+/// `2.5e-3` is `25` and `-4`. The power saturates for exponents too large for [i64].
+pub(crate) fn decimal_parts(literal: &str) -> (String, i64) {
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+        Some(e) => (&literal[..e], &literal[e + 1..]),
+        None => (literal, "0"),
+    };
+    let (integer, decimals) = match mantissa.find('.') {
+        Some(dot) => (&mantissa[..dot], &mantissa[dot + 1..]),
+        None => (mantissa, ""),
+    };
+
+    let exponent = exponent.parse::<i64>().unwrap_or_else(|_| {
+        if exponent.starts_with('-') {
+            i64::MIN
+        } else {
+            i64::MAX
+        }
+    });
+    (
+        format!("{}{}", integer, decimals),
+        exponent.saturating_sub(decimals.len() as i64),
+    )
+}
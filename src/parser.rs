@@ -1,157 +1,463 @@
 //! # Parser module
 //! Contains the functions used to parse the grammar.
-
-use std::iter::Peekable;
+//!
+//! The parser reads the tokens collected by [tokenize](crate::token::tokenize) or
+//! directly from a [Lexer](crate::token::Lexer), without collecting them first.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::ast::Expr;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::Lexer;
+//! let expr: Expr = Parser::from_tokens(Lexer::new("2x + 1")).parse().unwrap();
+//!
+//! assert_eq!(expr.to_string(), "2 * x + 1");
+//! # }
+//! ```
+
+use std::iter::{Map, Peekable};
 use std::vec::IntoIter;
 
-use crate::ast::{BinOpType, Expr, UnaryOpType};
+use crate::ast::{BinOpType, Expr, Number, UnaryOpType};
 use crate::errors::{ParserError, Result};
+use crate::numeric::Numeric;
+use crate::program::{Program, Statement};
+use crate::span::{Span, Spanned};
 use crate::token::Token;
 
+/// Tokens collected in a [Vec], see [Parser#new].
+type Collected<N> =
+    Map<IntoIter<Spanned<Token<N>>>, fn(Spanned<Token<N>>) -> Result<Spanned<Token<N>>>>;
+
+/// An expression with the token ending it, if any.
+type Terminated<N> = (Spanned<Expr<N>>, Option<Spanned<Token<N>>>);
+
+/// A parser of the tokens produced by `I`, the first error it produces stops the parsing.
 #[derive(Debug)]
-pub struct Parser {
-    token_stream: Peekable<IntoIter<Token>>,
-    output: Vec<Expr>,
-    operators: Vec<Token>,
+pub struct Parser<N = Number, I = Collected<N>>
+where
+    I: Iterator<Item = Result<Spanned<Token<N>>>>,
+{
+    token_stream: Peekable<I>,
+    output: Vec<Spanned<Expr<N>>>,
+    operators: Vec<Spanned<Token<N>>>,
+    /// Parenthesis currently open, in the same order as in the operator stack.
+    groups: Vec<Group>,
+    /// End of the last token read, used to locate errors at the end of the input.
+    end: usize,
+    /// Location of the operator or call of every node pushed to the output, in the order
+    /// they are built, which is the post-order of the expression.
+    spans: Vec<Span>,
+}
+
+/// A parenthesis group, possibly delimiting the arguments of a function call.
+#[derive(Debug)]
+struct Group {
+    /// Name of the function called, if any.
+    call: Option<Spanned<String>>,
+    /// Length of the output queue when the group was opened.
+    base: usize,
+    /// Number of commas read directly in the group.
+    commas: usize,
+}
+
+impl<N: Numeric> Parser<N> {
+    pub fn new(tokens: Vec<Spanned<Token<N>>>) -> Self {
+        Parser::from_tokens(tokens.into_iter().map(Ok as fn(_) -> _))
+    }
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+impl<N: Numeric, I: Iterator<Item = Result<Spanned<Token<N>>>>> Parser<N, I> {
+    pub fn from_tokens(tokens: I) -> Self {
         Self {
-            token_stream: tokens.into_iter().peekable(),
+            token_stream: tokens.peekable(),
             output: Vec::new(),
             operators: Vec::new(),
+            groups: Vec::new(),
+            end: 0,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Consume the input and parse it as a single expression.
+    pub fn parse(mut self) -> Result<Expr<N>> {
+        match self.parse_expr()? {
+            (expr, None) => Ok(expr.value),
+            (_, Some(end)) => Err(ParserError::NotAnExpression(end.span)),
+        }
+    }
+
+    /// Consume the input and parse it as a [Program], its statements are separated by
+    /// `;` or newlines.
+    pub fn parse_program(mut self) -> Result<Program<N>> {
+        let mut statements = Vec::new();
+        let mut spans = Vec::new();
+
+        loop {
+            // Skip the empty statements
+            while let Some(Ok(separator)) = self
+                .token_stream
+                .next_if(|token| matches!(token, Ok(t) if t.value == Token::Separator))
+            {
+                self.end = separator.span.end;
+            }
+            if self.token_stream.peek().is_none() {
+                return Ok(Program { statements, spans });
+            }
+
+            self.spans.clear();
+            let statement = match self.parse_expr()? {
+                (target, Some(equal)) if equal.value == Token::Equal => {
+                    // Only the value is evaluated and located
+                    self.spans.clear();
+                    let value = match self.parse_expr()? {
+                        (_, Some(equal)) if equal.value == Token::Equal => {
+                            return Err(ParserError::UnexpectedOperator("=".into(), equal.span))
+                        }
+                        (value, _) => value.value,
+                    };
+
+                    match target.value {
+                        Expr::Variable(name) => Statement::Assign(name, value),
+                        Expr::Call(name, args) => {
+                            let params = parameters(args)
+                                .ok_or(ParserError::InvalidAssignment(target.span))?;
+                            Statement::Define(name, params, value)
+                        }
+                        _ => return Err(ParserError::InvalidAssignment(target.span)),
+                    }
+                }
+                (expr, _) => Statement::Expr(expr.value),
+            };
+            statements.push(statement);
+            spans.push(std::mem::take(&mut self.spans));
         }
     }
-}
 
-impl Parser {
-    /// Consume the input and parse it using the Shunting-Yard algorithm implementation
-    /// from [Wikipedia](https://en.wikipedia.org/wiki/Shunting-yard_algorithm) slightly modified.
-    pub fn parse(mut self) -> Result<Expr> {
-        while let Some(token) = self.token_stream.next() {
+    /// Parse an expression up to the end of the input, a `=` or a separator, returned
+    /// if any. It uses the Shunting-Yard algorithm implementation from
+    /// [Wikipedia](https://en.wikipedia.org/wiki/Shunting-yard_algorithm) slightly modified.
+    fn parse_expr(&mut self) -> Result<Terminated<N>> {
+        let mut terminator = None;
+        // Whether the previous token ends an operand, that a postfix operator applies to
+        let mut after_operand = false;
+
+        while let Some(token) = self.token_stream.next().transpose()? {
+            self.end = token.span.end;
+            let ends_operand = token.value.is_atom()
+                || token.value == Token::ParenEnd
+                || token.value.is_postfix_op();
+
+            if matches!(token.value, Token::Equal | Token::Separator) {
+                terminator = Some(token);
+                break;
+            }
+
+            // Function call, the name is directly followed by its arguments
+            if let (
+                Token::Ident(name),
+                Some(Ok(Spanned {
+                    value: Token::ParenStart,
+                    ..
+                })),
+            ) = (&token.value, self.token_stream.peek())
+            {
+                let name = Spanned::new(name.clone(), token.span);
+                let paren = self.token_stream.next().unwrap()?;
+                self.end = paren.span.end;
+                self.open_group(paren, Some(name));
+
             // Number token
-            if token.is_atom() {
-                self.output.push(match token {
+            } else if token.value.is_atom() {
+                let expr = match token.value {
                     Token::Number(num) => Expr::Number(num),
+                    Token::Quantity(num, unit) => Expr::Quantity(num, unit),
                     Token::E => Expr::E,
                     Token::Pi => Expr::Pi,
+                    Token::I => Expr::I,
+                    Token::Ident(name) => Expr::Variable(name),
                     _ => unreachable!(),
-                });
-
-            // Operator token
-            } else if token.is_op() {
-                // Consume every operators with higher precedence
-                loop {
-                    // Exit condition
-                    if !self.operators.is_empty() {
-                        let last = self.operators.last().unwrap();
-
-                        if token != Token::ParenStart {
-                            let last_prec = last.op_prec();
-                            let current_prec = token.op_prec();
-
-                            if last_prec < current_prec
-                                || (last_prec == current_prec && !token.is_left_assoc())
-                            {
-                                break;
-                            }
-                        } else {
+                };
+                self.push_node(Spanned::new(expr, token.span), token.span);
+
+            // Postfix operator: binding tighter than every other one, it is applied right
+            // away to the last operand
+            } else if token.value.is_postfix_op() {
+                if !after_operand {
+                    return Err(ParserError::NotEnoughOperands(token.span));
+                }
+                self.apply_op(token)?;
+
+            // Operator token, a prefix operator has no left operand to consume
+            } else if token.value.is_op() {
+                if !token.value.is_unary_op() {
+                    // Consume every operators with higher precedence
+                    while let Some(last) = self.operators.last() {
+                        // Exit condition
+                        if last.value == Token::ParenStart {
                             break;
                         }
-                    } else {
-                        break;
-                    }
 
-                    // Loop content
+                        let last_prec = last.value.op_prec();
+                        let current_prec = token.value.op_prec();
 
-                    // Apply the operator
-                    let last = self.operators.pop().unwrap();
-                    self.apply_op(last)?;
+                        if last_prec < current_prec
+                            || (last_prec == current_prec && !token.value.is_left_assoc())
+                        {
+                            break;
+                        }
+
+                        // Apply the operator
+                        let last = self.operators.pop().unwrap();
+                        self.apply_op(last)?;
+                    }
                 }
 
                 // Append to the operator stack
                 self.operators.push(token);
 
-            // Handle parenthesis
-            } else if token == Token::ParenStart {
-                self.operators.push(token);
-            } else if token == Token::ParenEnd {
-                loop {
-                    // Loop condition
-                    if let Some(Token::ParenStart) = self.operators.last() {
-                        break;
-                    } else if let None = self.operators.last() {
+            // Conditional, the condition is complete and the `?` waits for its `:`
+            } else if token.value == Token::Question {
+                while let Some(last) = self.operators.last() {
+                    if last.value == Token::ParenStart
+                        || last.value.op_prec() <= token.value.op_prec()
+                    {
                         break;
                     }
 
-                    // Loop content
-
-                    // Apply the operator
                     let last = self.operators.pop().unwrap();
                     self.apply_op(last)?;
                 }
+                self.operators.push(token);
+            } else if token.value == Token::Colon {
+                // The first branch is complete, the `:` replaces the `?` to apply the
+                // conditional once the second one is
+                loop {
+                    match self.operators.pop() {
+                        Some(Spanned {
+                            value: Token::Question,
+                            ..
+                        }) => break,
+                        Some(last) if last.value != Token::ParenStart => self.apply_op(last)?,
+                        _ => return Err(ParserError::MismatchedConditional(token.span)),
+                    }
+                }
+                self.operators.push(token);
 
-                if let Some(Token::ParenStart) = self.operators.last() {
+            // Handle parenthesis
+            } else if token.value == Token::ParenStart {
+                self.open_group(token, None);
+            } else if token.value == Token::ParenEnd {
+                self.apply_until_paren()?;
+
+                if let Some(Spanned {
+                    value: Token::ParenStart,
+                    ..
+                }) = self.operators.last()
+                {
                     self.operators.pop().unwrap();
+                    self.close_group(token.span)?;
                 } else {
-                    Err(ParserError::MismatchedParenthesis)?
+                    return Err(ParserError::MismatchedParenthesis(')', token.span));
+                }
+
+            // Handle argument separators
+            } else if token.value == Token::Comma {
+                self.apply_until_paren()?;
+
+                match self.groups.last_mut() {
+                    Some(group) if group.call.is_some() => {
+                        // Every argument must be a single expression
+                        if self.output.len() - group.base != group.commas + 1 {
+                            return Err(ParserError::NotEnoughOperands(token.span));
+                        }
+                        group.commas += 1;
+                    }
+                    _ => return Err(ParserError::UnexpectedComma(token.span)),
                 }
             }
+
+            after_operand = ends_operand;
         }
 
-        // Apply the remaining operators of the stack
+        // Apply the remaining operators of the stack, every parenthesis must be closed
         while let Some(op) = self.operators.pop() {
-            if !op.is_paren() {
-                self.apply_op(op)?;
+            if op.value == Token::ParenStart {
+                return Err(ParserError::MismatchedParenthesis('(', op.span));
             }
+            self.apply_op(op)?;
         }
 
         // Sanity check the output queue must contain only one item
         if self.output.len() == 1 {
-            Ok(self.output.pop().unwrap())
+            Ok((self.output.pop().unwrap(), terminator))
         } else if self.output.is_empty() {
-            Err(ParserError::NotEnoughOperands)?
+            Err(ParserError::NotEnoughOperands(Span::new(
+                self.end, self.end,
+            )))
         } else {
-            Err(ParserError::TooMuchOperands)?
+            Err(ParserError::TooMuchOperands(self.output[1].span))
         }
     }
 
-    fn apply_op(&mut self, op: Token) -> Result<()> {
-        if op.is_unary_op() {
-            let operand = self.pop_operand()?;
-            let op = match op {
+    /// Apply every operator up to the last open parenthesis.
+    fn apply_until_paren(&mut self) -> Result<()> {
+        while let Some(last) = self.operators.last() {
+            if last.value == Token::ParenStart {
+                break;
+            }
+
+            let last = self.operators.pop().unwrap();
+            self.apply_op(last)?;
+        }
+
+        Ok(())
+    }
+
+    fn open_group(&mut self, paren: Spanned<Token<N>>, call: Option<Spanned<String>>) {
+        self.operators.push(paren);
+        self.groups.push(Group {
+            call,
+            base: self.output.len(),
+            commas: 0,
+        });
+    }
+
+    /// Close the last group, its opening parenthesis must already be popped from the
+    /// operator stack. `end` locates the closing parenthesis.
+    fn close_group(&mut self, end: Span) -> Result<()> {
+        let group = self.groups.pop().unwrap();
+
+        if let Some(name) = group.call {
+            let count = self.output.len() - group.base;
+
+            // Either no arguments at all or one per comma plus the last one
+            if count < group.commas + 1 && (count > 0 || group.commas > 0) {
+                return Err(ParserError::NotEnoughOperands(end));
+            } else if count > group.commas + 1 {
+                return Err(ParserError::TooMuchOperands(
+                    self.output[group.base + group.commas + 1].span,
+                ));
+            }
+
+            let args = self
+                .output
+                .split_off(group.base)
+                .into_iter()
+                .map(|arg| arg.value)
+                .collect();
+            let span = name.span.to(end);
+            self.push_node(Spanned::new(Expr::Call(name.value, args), span), span);
+        }
+
+        Ok(())
+    }
+
+    fn apply_op(&mut self, op: Spanned<Token<N>>) -> Result<()> {
+        if op.value == Token::Question {
+            return Err(ParserError::MismatchedConditional(op.span));
+        } else if op.value == Token::Colon {
+            let otherwise = self.pop_operand(op.span)?;
+            let then = self.pop_operand(op.span)?;
+            let condition = self.pop_operand(op.span)?;
+
+            let span = condition.span.to(otherwise.span);
+            let expr = Expr::Conditional(
+                condition.value.boxed(),
+                then.value.boxed(),
+                otherwise.value.boxed(),
+            );
+            self.push_node(Spanned::new(expr, span), span);
+        } else if op.value.is_unary_op() {
+            let operand = self.pop_operand(op.span)?;
+            let expr_op = match op.value {
                 Token::UnaryMinus => UnaryOpType::Negate,
+                Token::Not => UnaryOpType::Not,
                 _ => UnaryOpType::Noop,
             };
 
-            self.output.push(Expr::UnaryOp(op, Box::new(operand)));
+            let span = op.span.to(operand.span);
+            let expr = Expr::UnaryOp(expr_op, Box::new(operand.value));
+            self.push_node(Spanned::new(expr, span), op.span);
+        } else if op.value.is_postfix_op() {
+            let operand = self.pop_operand(op.span)?;
+            let expr_op = match op.value {
+                Token::Bang => UnaryOpType::Factorial,
+                _ => UnaryOpType::Percent,
+            };
+
+            let span = operand.span.to(op.span);
+            let expr = Expr::UnaryOp(expr_op, Box::new(operand.value));
+            self.push_node(Spanned::new(expr, span), op.span);
         } else {
             /* else if op.is_bin_op()*/
-            let right = self.pop_operand()?;
-            let left = self.pop_operand()?;
-
-            let expr_op = match op {
-                Token::Plus => BinOpType::Add,
-                Token::Minus => BinOpType::Sub,
-                Token::Times => BinOpType::Mul,
-                Token::Slash => BinOpType::Div,
-                Token::TimesTimes => BinOpType::Pow,
+            let right = self.pop_operand(op.span)?;
+            let left = self.pop_operand(op.span)?;
+
+            let span = left.span.to(right.span);
+            let (left, right) = (Box::new(left.value), Box::new(right.value));
+
+            let expr = match op.value {
+                Token::In => Expr::Convert(left, right),
+                Token::Plus => Expr::BinOp(left, BinOpType::Add, right),
+                Token::Minus => Expr::BinOp(left, BinOpType::Sub, right),
+                Token::Times => Expr::BinOp(left, BinOpType::Mul, right),
+                Token::Slash => Expr::BinOp(left, BinOpType::Div, right),
+                Token::Percent => Expr::BinOp(left, BinOpType::Mod, right),
+                Token::SlashSlash => Expr::BinOp(left, BinOpType::FloorDiv, right),
+                Token::TimesTimes => Expr::BinOp(left, BinOpType::Pow, right),
+                Token::EqualEqual => Expr::BinOp(left, BinOpType::Eq, right),
+                Token::NotEqual => Expr::BinOp(left, BinOpType::Ne, right),
+                Token::Less => Expr::BinOp(left, BinOpType::Lt, right),
+                Token::LessEqual => Expr::BinOp(left, BinOpType::Le, right),
+                Token::Greater => Expr::BinOp(left, BinOpType::Gt, right),
+                Token::GreaterEqual => Expr::BinOp(left, BinOpType::Ge, right),
+                Token::And => Expr::BinOp(left, BinOpType::And, right),
+                Token::Or => Expr::BinOp(left, BinOpType::Or, right),
                 _ => unreachable!(),
             };
 
-            self.output
-                .push(Expr::BinOp(Box::new(left), expr_op, Box::new(right)))
+            self.push_node(Spanned::new(expr, span), op.span);
         }
 
         Ok(())
     }
 
-    fn pop_operand(&mut self) -> Result<Expr> {
-        self.output
-            .pop()
-            .ok_or_else(|| ParserError::NotEnoughOperands)
+    /// Push a node to the output queue, `at` locates its operator, or the whole node if
+    /// it has none.
+    fn push_node(&mut self, node: Spanned<Expr<N>>, at: Span) {
+        self.output.push(node);
+        self.spans.push(at);
+    }
+
+    /// Pop the last operand of the output queue, `op_span` locates the operator needing it.
+    /// Operands of the enclosing groups and previous arguments can't be used from inside a group.
+    fn pop_operand(&mut self, op_span: Span) -> Result<Spanned<Expr<N>>> {
+        let base = self
+            .groups
+            .last()
+            .map_or(0, |group| group.base + group.commas);
+        if self.output.len() > base {
+            Ok(self.output.pop().unwrap())
+        } else {
+            Err(ParserError::NotEnoughOperands(op_span))
+        }
+    }
+}
+
+/// Names of the parameters of a function definition, `None` unless they are distinct
+/// variables.
+fn parameters<N>(args: Vec<Expr<N>>) -> Option<Vec<String>> {
+    let mut params = Vec::<String>::new();
+    for arg in args {
+        match arg {
+            Expr::Variable(name) if !params.contains(&name) => params.push(name),
+            _ => return None,
+        }
     }
+
+    Some(params)
 }
 
 #[cfg(test)]
@@ -159,17 +465,18 @@ mod tests {
     use crate::ast::{BinOpType, Expr, UnaryOpType};
     use crate::errors::ParserError;
     use crate::parser::Parser;
-    use crate::token::tokenize;
+    use crate::span::Span;
+    use crate::token::{tokenize, Lexer};
 
     #[test]
     fn parse_unary_expr() -> Result<(), ParserError> {
-        let parser = Parser::new(tokenize("+1".into())?);
+        let parser = Parser::new(tokenize("+1")?);
         assert_eq!(
             parser.parse()?,
             Expr::UnaryOp(UnaryOpType::Noop, Box::new(Expr::Number(1.)))
         );
 
-        let parser = Parser::new(tokenize("-1".into())?);
+        let parser = Parser::new(tokenize("-1")?);
         assert_eq!(
             parser.parse()?,
             Expr::UnaryOp(UnaryOpType::Negate, Box::new(Expr::Number(1.)))
@@ -180,7 +487,7 @@ mod tests {
 
     #[test]
     fn parse_bin_expr() {
-        let parser = Parser::new(tokenize("1+2".into()).unwrap());
+        let parser = Parser::new(tokenize("1+2").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -190,7 +497,7 @@ mod tests {
             )
         );
 
-        let parser = Parser::new(tokenize("1-2".into()).unwrap());
+        let parser = Parser::new(tokenize("1-2").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -200,7 +507,7 @@ mod tests {
             )
         );
 
-        let parser = Parser::new(tokenize("1*2".into()).unwrap());
+        let parser = Parser::new(tokenize("1*2").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -210,7 +517,7 @@ mod tests {
             )
         );
 
-        let parser = Parser::new(tokenize("1/2".into()).unwrap());
+        let parser = Parser::new(tokenize("1/2").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -220,7 +527,7 @@ mod tests {
             )
         );
 
-        let parser = Parser::new(tokenize("1**2".into()).unwrap());
+        let parser = Parser::new(tokenize("1**2").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -233,7 +540,7 @@ mod tests {
 
     #[test]
     fn bin_op_precedence() {
-        let parser = Parser::new(tokenize("1**2**3/4*5+6".into()).unwrap());
+        let parser = Parser::new(tokenize("1**2**3/4*5+6").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -264,9 +571,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bin_op_associativity() {
+        let parse = |source: &str| Parser::new(tokenize(source).unwrap()).parse().unwrap();
+        let num = |value: f64| Expr::Number(value).boxed();
+
+        // `*`, `/`, `%` and `//` share a precedence level and are all left associative
+        assert_eq!(
+            parse("8 / 4 // 2 % 3 / 5"),
+            Expr::BinOp(
+                Expr::BinOp(
+                    Expr::BinOp(
+                        Expr::BinOp(num(8.), BinOpType::Div, num(4.)).boxed(),
+                        BinOpType::FloorDiv,
+                        num(2.)
+                    )
+                    .boxed(),
+                    BinOpType::Mod,
+                    num(3.)
+                )
+                .boxed(),
+                BinOpType::Div,
+                num(5.)
+            )
+        );
+        assert_eq!(
+            parse("8 / 4 / 2"),
+            Expr::BinOp(
+                Expr::BinOp(num(8.), BinOpType::Div, num(4.)).boxed(),
+                BinOpType::Div,
+                num(2.)
+            )
+        );
+    }
+
     #[test]
     fn parens_hell() {
-        let parser = Parser::new(tokenize("((1+2)*((3/4)/(5**6))".into()).unwrap());
+        let parser = Parser::new(tokenize("((1+2)*((3/4)/(5**6)))").unwrap());
         assert_eq!(
             parser.parse().unwrap(),
             Expr::BinOp(
@@ -296,4 +637,268 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn error_spans() {
+        let parser = Parser::new(tokenize("(1+2))*3").unwrap());
+        match parser.parse() {
+            Err(ParserError::MismatchedParenthesis(')', span)) => {
+                assert_eq!(span, Span::new(5, 6))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The innermost unclosed parenthesis is reported
+        let parser = Parser::new(tokenize("((1+2)*(3").unwrap());
+        match parser.parse() {
+            Err(ParserError::MismatchedParenthesis('(', span)) => assert_eq!(span, Span::new(7, 8)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let parser = Parser::new(tokenize("2 *").unwrap());
+        match parser.parse() {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let parser = Parser::new(tokenize("  ").unwrap());
+        match parser.parse() {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(0, 0)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let parser = Parser::new(tokenize("x = 1").unwrap());
+        match parser.parse() {
+            Err(ParserError::NotAnExpression(span)) => assert_eq!(span, Span::new(2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_conditionals() {
+        let parse = |source: &str| Parser::new(tokenize(source).unwrap()).parse();
+        let var = |name: &str| Expr::Variable(name.into()).boxed();
+
+        assert_eq!(
+            parse("x > 100 ? x*0.9 : x").unwrap(),
+            Expr::Conditional(
+                Expr::BinOp(var("x"), BinOpType::Gt, Expr::Number(100.).boxed()).boxed(),
+                Expr::BinOp(var("x"), BinOpType::Mul, Expr::Number(0.9).boxed()).boxed(),
+                var("x"),
+            )
+        );
+        // Right associative, in both branches
+        assert_eq!(
+            parse("a ? b ? c : d : h ? f : g").unwrap(),
+            Expr::Conditional(
+                var("a"),
+                Expr::Conditional(var("b"), var("c"), var("d")).boxed(),
+                Expr::Conditional(var("h"), var("f"), var("g")).boxed(),
+            )
+        );
+        assert_eq!(
+            parse("not a < b and c or d").unwrap(),
+            Expr::BinOp(
+                Expr::BinOp(
+                    Expr::UnaryOp(
+                        UnaryOpType::Not,
+                        Expr::BinOp(var("a"), BinOpType::Lt, var("b")).boxed()
+                    )
+                    .boxed(),
+                    BinOpType::And,
+                    var("c")
+                )
+                .boxed(),
+                BinOpType::Or,
+                var("d")
+            )
+        );
+        assert_eq!(
+            parse("f(a ? -b : c, 1 + not d)").unwrap(),
+            Expr::Call(
+                "f".into(),
+                vec![
+                    Expr::Conditional(
+                        var("a"),
+                        Expr::UnaryOp(UnaryOpType::Negate, var("b")).boxed(),
+                        var("c")
+                    ),
+                    Expr::BinOp(
+                        Expr::Number(1.).boxed(),
+                        BinOpType::Add,
+                        Expr::UnaryOp(UnaryOpType::Not, var("d")).boxed()
+                    ),
+                ]
+            )
+        );
+
+        for (source, span) in [
+            ("a ? b", Span::new(2, 3)),
+            ("a : b", Span::new(2, 3)),
+            ("(a ? b) : c", Span::new(3, 4)),
+            ("a ? b : c : d", Span::new(10, 11)),
+            ("f(a ? b, c)", Span::new(4, 5)),
+        ] {
+            match parse(source) {
+                Err(ParserError::MismatchedConditional(found)) => assert_eq!(found, span),
+                other => panic!("unexpected result for {}: {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_postfix() {
+        let parse = |source: &str| Parser::new(tokenize(source).unwrap()).parse();
+        let num = |value: f64| Expr::Number(value).boxed();
+        let unary = |op, operand| Expr::UnaryOp(op, operand).boxed();
+
+        assert_eq!(
+            parse("-3!").unwrap(),
+            *unary(UnaryOpType::Negate, unary(UnaryOpType::Factorial, num(3.)))
+        );
+        assert_eq!(
+            parse("2**3!%").unwrap(),
+            Expr::BinOp(
+                num(2.),
+                BinOpType::Pow,
+                unary(UnaryOpType::Percent, unary(UnaryOpType::Factorial, num(3.)))
+            )
+        );
+        assert_eq!(
+            parse("x + (1 + 2)%").unwrap(),
+            Expr::BinOp(
+                Expr::Variable("x".into()).boxed(),
+                BinOpType::Add,
+                unary(
+                    UnaryOpType::Percent,
+                    Expr::BinOp(num(1.), BinOpType::Add, num(2.)).boxed()
+                )
+            )
+        );
+        assert_eq!(
+            parse("7 % 4 // 2 * 3").unwrap(),
+            Expr::BinOp(
+                Expr::BinOp(
+                    Expr::BinOp(num(7.), BinOpType::Mod, num(4.)).boxed(),
+                    BinOpType::FloorDiv,
+                    num(2.)
+                )
+                .boxed(),
+                BinOpType::Mul,
+                num(3.)
+            )
+        );
+
+        match parse("1 + !2") {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(4, 5)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match parse("f(1, %)") {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(5, 6)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_variables() {
+        let parser = Parser::new(tokenize("2x").unwrap());
+        assert_eq!(
+            parser.parse().unwrap(),
+            Expr::BinOp(
+                Expr::Number(2.).boxed(),
+                BinOpType::Mul,
+                Expr::Variable("x".into()).boxed()
+            )
+        );
+    }
+
+    #[test]
+    fn parse_calls() {
+        let parser = Parser::new(tokenize("max(1, 2+3, sin(x))").unwrap());
+        assert_eq!(
+            parser.parse().unwrap(),
+            Expr::Call(
+                "max".into(),
+                vec![
+                    Expr::Number(1.),
+                    Expr::BinOp(
+                        Expr::Number(2.).boxed(),
+                        BinOpType::Add,
+                        Expr::Number(3.).boxed()
+                    ),
+                    Expr::Call("sin".into(), vec![Expr::Variable("x".into())]),
+                ]
+            )
+        );
+
+        let parser = Parser::new(tokenize("2f()").unwrap());
+        assert_eq!(
+            parser.parse().unwrap(),
+            Expr::BinOp(
+                Expr::Number(2.).boxed(),
+                BinOpType::Mul,
+                Expr::Call("f".into(), vec![]).boxed()
+            )
+        );
+
+        // Unclosed calls are errors
+        let parser = Parser::new(tokenize("-sqrt(4").unwrap());
+        match parser.parse() {
+            Err(ParserError::MismatchedParenthesis('(', span)) => assert_eq!(span, Span::new(5, 6)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_error_spans() {
+        let parser = Parser::new(tokenize("(1, 2)").unwrap());
+        match parser.parse() {
+            Err(ParserError::UnexpectedComma(span)) => assert_eq!(span, Span::new(2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let parser = Parser::new(tokenize("f(1,)").unwrap());
+        match parser.parse() {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(4, 5)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let parser = Parser::new(tokenize("f(,1)").unwrap());
+        match parser.parse() {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Previous arguments can't be used as operands
+        let parser = Parser::new(tokenize("f(1, -)").unwrap());
+        match parser.parse() {
+            Err(ParserError::NotEnoughOperands(span)) => assert_eq!(span, Span::new(5, 6)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_from_lexer() {
+        let parser = Parser::from_tokens(Lexer::new("2(x + 1)"));
+        assert_eq!(
+            parser.parse().unwrap(),
+            Expr::BinOp(
+                Expr::Number(2.).boxed(),
+                BinOpType::Mul,
+                Expr::BinOp(
+                    Expr::Variable("x".into()).boxed(),
+                    BinOpType::Add,
+                    Expr::Number(1.).boxed()
+                )
+                .boxed()
+            )
+        );
+
+        // Errors of the lexer stop the parsing
+        let parser = Parser::<f64, _>::from_tokens(Lexer::new("1 + 2 $ 3"));
+        match parser.parse() {
+            Err(ParserError::UnexpectedChar('$', span)) => assert_eq!(span, Span::new(6, 7)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,265 @@
+//! # Program module
+//! Contains the [Program]s, lists of statements evaluated in order against the same
+//! [Environment], for small calculation scripts.
+//!
+//! Statements are separated by `;` or newlines outside parentheses, and are either an
+//! expression, the assignment of one to a variable, like `x = 3`, or the definition of a
+//! function, like `f(x, y) = x**2 + y`. The variables keep the dimension of the quantity assigned to
+//! them, and the body of a function sees its parameters over the variables bound when
+//! it is called.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::eval::Environment;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let program = Parser::new(tokenize("x = 3; y = x**2 + 1\ny/2").unwrap())
+//!     .parse_program()
+//!     .unwrap();
+//!
+//! let mut env = Environment::new();
+//! assert_eq!(program.eval_in(&mut env).unwrap().unwrap().value, 5.);
+//! assert_eq!(env.get("y"), Some(10.));
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{Expr, Number};
+use crate::errors::{Error, EvalError, EvalResult};
+use crate::eval::{evaluate_located, Environment, Function};
+use crate::numeric::Numeric;
+use crate::span::Span;
+use crate::units::Quantity;
+
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Statement<N = Number> {
+    /// Bind the variable to the value of the expression.
+    Assign(String, Expr<N>),
+    /// Define the function of the given parameters and body.
+    Define(String, Vec<String>, Expr<N>),
+    Expr(Expr<N>),
+}
+
+impl<N: Display> Display for Statement<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Assign(name, value) => write!(f, "{} = {}", name, value),
+            Self::Define(name, params, body) => {
+                write!(f, "{}({}) = {}", name, params.join(", "), body)
+            }
+            Self::Expr(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program<N = Number> {
+    pub statements: Vec<Statement<N>>,
+    /// Location in the source of the nodes of each statement, in the post-order of its
+    /// expression, empty if unknown. It locates the evaluation errors.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub spans: Vec<Vec<Span>>,
+}
+
+/// Programs are compared by their statements only, wherever they are read from.
+impl<N: PartialEq> PartialEq for Program<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+    }
+}
+
+impl<N: Numeric> Program<N> {
+    /// Evaluate the statements in order, binding the assigned variables and defined
+    /// functions in `env`. Returns the value of the last statement that has one, an
+    /// assignment having the assigned value, or `None` if there is none.
+    ///
+    /// The evaluation stops at the first error, the previous assignments are kept.
+    pub fn eval_in(&self, env: &mut Environment<N>) -> EvalResult<Option<Quantity<N>>> {
+        self.run(env).map_err(|(error, _)| error)
+    }
+
+    /// Same as [Program#eval_in], with the location of the operator or call raising the
+    /// error if it is known.
+    pub fn eval_located_in(&self, env: &mut Environment<N>) -> Result<Option<Quantity<N>>, Error> {
+        self.run(env)
+            .map_err(|(error, span)| Error::Eval(error, span))
+    }
+
+    fn run(
+        &self,
+        env: &mut Environment<N>,
+    ) -> Result<Option<Quantity<N>>, (EvalError, Option<Span>)> {
+        let mut last = None;
+
+        for (index, statement) in self.statements.iter().enumerate() {
+            let evaluate = |expr: &Expr<N>, env: &Environment<N>| {
+                evaluate_located(expr, env).map_err(|located| {
+                    let spans = self.spans.get(index);
+                    let span = spans.and_then(|spans| spans.get(located.node(expr)));
+                    (located.error, span.copied())
+                })
+            };
+
+            last = Some(match statement {
+                Statement::Assign(name, value) => {
+                    let value = evaluate(value, env)?;
+                    env.set_quantity(name.clone(), value.clone());
+                    value
+                }
+                Statement::Define(name, params, body) => {
+                    let function = Function {
+                        params: params.clone(),
+                        body: body.clone(),
+                    };
+                    env.define(name.clone(), function);
+                    continue;
+                }
+                Statement::Expr(expr) => evaluate(expr, env)?,
+            });
+        }
+
+        Ok(last)
+    }
+}
+
+impl<N: Display> Display for Program<N> {
+    /// Formatted with a statement per line.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, statement) in self.statements.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", statement)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::{EvalError, ParserError};
+    use crate::eval::{Arity, Environment, MAX_CALL_DEPTH};
+    use crate::parser::Parser;
+    use crate::program::{Program, Statement};
+    use crate::span::Span;
+    use crate::token::tokenize;
+
+    fn parse(source: &str) -> Result<Program, ParserError> {
+        Parser::new(tokenize(source).unwrap()).parse_program()
+    }
+
+    fn run(source: &str) -> Result<Option<String>, EvalError> {
+        let result = parse(source).unwrap().eval_in(&mut Environment::new())?;
+        Ok(result.map(|quantity| quantity.to_string()))
+    }
+
+    #[test]
+    fn parse_statements() {
+        let program = parse("x = 3; y = x**2 + 1\n\n f(a, b) = a*b; y/2 ;").unwrap();
+        assert_eq!(program.statements.len(), 4);
+        assert!(matches!(&program.statements[0], Statement::Assign(name, _) if name == "x"));
+        assert!(matches!(
+            &program.statements[2],
+            Statement::Define(name, params, _) if name == "f" && *params == ["a", "b"]
+        ));
+        assert_eq!(
+            program.to_string(),
+            "x = 3\ny = x ** 2 + 1\nf(a, b) = a * b\ny / 2"
+        );
+
+        assert_eq!(parse(" ;\n").unwrap(), Program::default());
+        assert!(matches!(
+            parse("2x = 1"),
+            Err(ParserError::InvalidAssignment(span)) if span == Span::new(0, 2)
+        ));
+        assert!(matches!(
+            parse("x = y = 1"),
+            Err(ParserError::UnexpectedOperator(op, span)) if op == "=" && span == Span::new(6, 7)
+        ));
+        assert!(matches!(
+            parse("f(x, x) = 1"),
+            Err(ParserError::InvalidAssignment(span)) if span == Span::new(0, 7)
+        ));
+        assert!(matches!(
+            parse("f(2) = 1"),
+            Err(ParserError::InvalidAssignment(_))
+        ));
+        assert!(matches!(
+            parse("x = ;"),
+            Err(ParserError::NotEnoughOperands(_))
+        ));
+    }
+
+    #[test]
+    fn eval_statements() {
+        assert_eq!(run("x = 3; y = x**2 + 1; y/2"), Ok(Some("5".into())));
+        assert_eq!(run("x = 1\nx = x + 1\nx = x * 10"), Ok(Some("20".into())));
+        assert_eq!(
+            run("d = 3 km; t = 20 min\nd/t in km/h"),
+            Ok(Some("9 km/h".into()))
+        );
+        // An expression in parentheses continues on the next lines
+        assert_eq!(run("x = (1 +\n2)\nmax(x,\n 4)"), Ok(Some("4".into())));
+        assert_eq!(run(""), Ok(None));
+        assert_eq!(
+            run("x = 1; y + x"),
+            Err(EvalError::UnboundVariable("y".into()))
+        );
+
+        // The assignments before an error are kept
+        let mut env = Environment::new();
+        let program = parse("x = 2; y = 1/0; z = 3").unwrap();
+        assert_eq!(program.eval_in(&mut env), Err(EvalError::DivisionByZero));
+        assert_eq!(env.variables(), vec![("x", &2.)]);
+    }
+
+    #[test]
+    fn user_functions() {
+        assert_eq!(run("f(x, y) = x**2 + y; f(2, 3)"), Ok(Some("7".into())));
+        assert_eq!(run("f() = 1; f() + 1"), Ok(Some("2".into())));
+        assert_eq!(run("f(x) = x"), Ok(None));
+        // Builtins can be shadowed
+        assert_eq!(run("sin(x) = x; sin(2)"), Ok(Some("2".into())));
+        assert_eq!(
+            run("speed(d, t) = d / t in km/h; speed(3 km, 20 min)"),
+            Ok(Some("9 km/h".into()))
+        );
+
+        // Parameters shadow the globals, which are read when the function is called
+        assert_eq!(
+            run("x = 10; f(x) = x * 2; g(y) = x + y; x = 20; f(3) + g(1)"),
+            Ok(Some("27".into()))
+        );
+        // The parameters of the caller aren't visible from the callee
+        assert_eq!(
+            run("g() = q; f(q) = g(); f(1)"),
+            Err(EvalError::UnboundVariable("q".into()))
+        );
+
+        assert_eq!(
+            run("f(x) = x; f(1, 2)"),
+            Err(EvalError::WrongArity {
+                name: "f".into(),
+                expected: Arity::exactly(1),
+                got: 2
+            })
+        );
+        assert_eq!(
+            run("f(x) = g(x); f(1)"),
+            Err(EvalError::UnknownFunction("g".into()))
+        );
+        assert_eq!(
+            run("f(x) = f(x + 1); f(1)"),
+            Err(EvalError::RecursionLimit(MAX_CALL_DEPTH))
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! # Rational module
+//! Contains the [Rational] number type, computing exactly with arbitrary-precision
+//! fractions as long as possible.
+//!
+//! Irrational operations (`e`, `pi`, non-integer powers and the builtin functions
+//! without an exact counterpart) fall back to floating point, and so does every
+//! operation involving one of their results.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::eval::Eval;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::rational::Rational;
+//! # use rust_calculator::token::tokenize_as;
+//! let expr = Parser::new(tokenize_as::<Rational>("1/3 + 1/6").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! assert_eq!(expr.try_eval().unwrap().to_string(), "1/2");
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Pow, Signed, ToPrimitive, Zero};
+
+use crate::numeric::{decimal_parts, Numeric};
+
+/// Largest number of bits of the numerator and denominator an exact power may produce,
+/// bigger powers are approximated instead.
+const MAX_EXACT_POW_BITS: u64 = 1 << 20;
+
+/// Largest power of ten of an exact literal, its value stays within [MAX_EXACT_POW_BITS].
+const MAX_EXACT_POWER: u64 = MAX_EXACT_POW_BITS / 4;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Rational {
+    Exact(BigRational),
+    Approx(f64),
+}
+
+impl Rational {
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Self::Exact(_))
+    }
+
+    /// Apply `exact` if both operands are exact, `approx` otherwise.
+    fn combine(
+        self,
+        other: Self,
+        exact: impl FnOnce(BigRational, BigRational) -> BigRational,
+        approx: impl FnOnce(f64, f64) -> f64,
+    ) -> Self {
+        match (self, other) {
+            (Self::Exact(left), Self::Exact(right)) => Self::Exact(exact(left, right)),
+            (left, right) => Self::Approx(approx(left.to_f64(), right.to_f64())),
+        }
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(ratio) => Display::fmt(ratio, f),
+            Self::Approx(float) => Display::fmt(float, f),
+        }
+    }
+}
+
+impl Add for Rational {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.combine(other, |l, r| l + r, |l, r| l + r)
+    }
+}
+
+impl Sub for Rational {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.combine(other, |l, r| l - r, |l, r| l - r)
+    }
+}
+
+impl Mul for Rational {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.combine(other, |l, r| l * r, |l, r| l * r)
+    }
+}
+
+impl Div for Rational {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        // Exact divisions by zero would panic, follow IEEE 754 instead
+        if other.is_zero() {
+            return Self::Approx(self.to_f64() / 0.);
+        }
+
+        self.combine(other, |l, r| l / r, |l, r| l / r)
+    }
+}
+
+impl Neg for Rational {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        match self {
+            Self::Exact(ratio) => Self::Exact(-ratio),
+            Self::Approx(float) => Self::Approx(-float),
+        }
+    }
+}
+
+impl Numeric for Rational {
+    /// Exact unless the power of ten is beyond [MAX_EXACT_POWER].
+    fn from_literal(literal: &str) -> Self {
+        let (digits, power) = decimal_parts(literal);
+        if power.unsigned_abs() > MAX_EXACT_POWER {
+            return Self::Approx(literal.parse().unwrap());
+        }
+
+        // The tokenizer only produces valid literals
+        let digits = digits.parse::<BigInt>().unwrap();
+        let scale = BigInt::from(10).pow(power.unsigned_abs() as u32);
+        Self::Exact(if power < 0 {
+            BigRational::new(digits, scale)
+        } else {
+            BigRational::from_integer(digits * scale)
+        })
+    }
+
+    fn from_f64(value: f64) -> Self {
+        Self::Approx(value)
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Self::Exact(ratio) => ratio.to_f64().unwrap_or(f64::NAN),
+            Self::Approx(float) => *float,
+        }
+    }
+
+    fn e() -> Self {
+        Self::Approx(std::f64::consts::E)
+    }
+
+    fn pi() -> Self {
+        Self::Approx(std::f64::consts::PI)
+    }
+
+    fn pow(&self, exponent: &Self) -> Self {
+        if let (Self::Exact(base), Self::Exact(exponent)) = (self, exponent) {
+            let exact_exponent = exponent
+                .to_integer()
+                .to_i32()
+                .filter(|_| exponent.is_integer());
+
+            if let Some(exponent) = exact_exponent {
+                let bits = (base.numer().bits() + base.denom().bits())
+                    * u64::from(exponent.unsigned_abs());
+
+                if !(base.is_zero() && exponent < 0) && bits <= MAX_EXACT_POW_BITS {
+                    return Self::Exact(base.pow(exponent));
+                }
+            }
+        }
+
+        Self::Approx(self.to_f64().powf(exponent.to_f64()))
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Self::Exact(ratio) => ratio.is_zero(),
+            Self::Approx(float) => *float == 0.,
+        }
+    }
+
+    fn is_negative(&self) -> bool {
+        match self {
+            Self::Exact(ratio) => ratio.is_negative(),
+            Self::Approx(float) => *float < 0.,
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        match self {
+            Self::Exact(ratio) => ratio.is_integer(),
+            Self::Approx(float) => float.fract() == 0.,
+        }
+    }
+
+    fn is_finite(&self) -> bool {
+        match self {
+            Self::Exact(_) => true,
+            Self::Approx(float) => float.is_finite(),
+        }
+    }
+
+    fn is_nan(&self) -> bool {
+        match self {
+            Self::Exact(_) => false,
+            Self::Approx(float) => float.is_nan(),
+        }
+    }
+
+    /// The rounding functions, `abs`, `sign`, `min` and `max` are exact.
+    /// Exact, the product of big integers doesn't need reducing.
+    fn factorial(n: u32) -> Option<Self> {
+        let mut product = BigInt::from(1);
+        for factor in 2..=n {
+            product *= factor;
+        }
+        Some(Self::Exact(BigRational::from_integer(product)))
+    }
+
+    fn apply(name: &str, args: &[Self]) -> Option<Self> {
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Self::Exact(ratio) => Some(ratio),
+                Self::Approx(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let result = match (name, args.as_slice()) {
+            ("abs", [x]) => x.abs(),
+            ("sign", [x]) => x.signum(),
+            ("floor", [x]) => x.floor(),
+            ("ceil", [x]) => x.ceil(),
+            ("round", [x]) => x.round(),
+            ("trunc", [x]) => x.trunc(),
+            ("min", [_, ..]) => args.iter().copied().min().cloned()?,
+            ("max", [_, ..]) => args.iter().copied().max().cloned()?,
+            _ => return None,
+        };
+
+        Some(Self::Exact(result))
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self {
+        Self::Exact(BigRational::from_integer(value.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_rational::BigRational;
+
+    use crate::errors::EvalError;
+    use crate::eval::Eval;
+    use crate::numeric::Numeric;
+    use crate::parser::Parser;
+    use crate::rational::Rational;
+    use crate::token::tokenize_as;
+
+    fn eval(source: &str) -> Result<Rational, EvalError> {
+        Parser::new(tokenize_as::<Rational>(source).unwrap())
+            .parse()
+            .unwrap()
+            .try_eval()
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Rational {
+        Rational::Exact(BigRational::new(numer.into(), denom.into()))
+    }
+
+    #[test]
+    fn read_literals() {
+        assert_eq!(Rational::from_literal("12.345"), ratio(12345, 1000));
+        assert_eq!(Rational::from_literal(".5"), ratio(1, 2));
+        assert_eq!(Rational::from_literal("12."), ratio(12, 1));
+        assert_eq!(Rational::from_literal("1.5e-9"), ratio(3, 2_000_000_000));
+        assert_eq!(Rational::from_literal("2E3"), ratio(2000, 1));
+        assert_eq!(
+            Rational::from_literal("1e999999"),
+            Rational::Approx(f64::INFINITY)
+        );
+        assert_eq!(
+            eval("0xffffffffffffffffffff + 1").unwrap().to_string(),
+            "1208925819614629174706176"
+        );
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        assert_eq!(eval("1/3 + 1/6"), Ok(ratio(1, 2)));
+        assert_eq!(eval("0.1 + 0.2"), Ok(ratio(3, 10)));
+        assert_eq!(eval("(2/3) ** -2"), Ok(ratio(9, 4)));
+        assert_eq!(eval("-(1/2)"), Ok(ratio(-1, 2)));
+        assert_eq!(eval("max(1/3, 1/2, abs(-1/4))"), Ok(ratio(1, 2)));
+        assert_eq!(eval("floor(7/2)"), Ok(ratio(3, 1)));
+        assert_eq!(
+            eval("2 ** 100").unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(eval("1/3 - 1/2").unwrap().to_string(), "-1/6");
+        assert_eq!(
+            eval("25!").unwrap().to_string(),
+            "15511210043330985984000000"
+        );
+        assert_eq!(
+            eval("171!").unwrap().to_string(),
+            "1241018070217667823424840524103103992616605577501693185388951803611996075221691752992751978120487585576464959501670387052809889858690710767331242032218484364310473577889968548278290754541561964852153468318044293239598173696899657235903947616152278558180061176365108428800000000000000000000000000000000000000000"
+        );
+        assert_eq!(eval("10000!").unwrap().to_string().len(), 35660);
+    }
+
+    #[test]
+    fn approximate_fallback() {
+        assert_eq!(eval("2pi"), Ok(Rational::Approx(2. * std::f64::consts::PI)));
+        assert_eq!(eval("4 ** 0.5"), Ok(Rational::Approx(2.)));
+        assert_eq!(eval("sqrt(1/4) + 1"), Ok(Rational::Approx(1.5)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1/(1/2 - 0.5)"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("0 ** -1"), Err(EvalError::DivisionByZero));
+
+        // Unchecked evaluation must not panic
+        let expr = Parser::new(tokenize_as::<Rational>("1/0").unwrap())
+            .parse()
+            .unwrap();
+        assert_eq!(expr.eval(), Rational::Approx(f64::INFINITY));
+    }
+}
@@ -0,0 +1,218 @@
+//! # REPL module
+//! Interactive mode of the binary, started when no expression is given.
+
+use std::io::{self, Write};
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use rust_calculator::diagnostic::Diagnostic;
+use rust_calculator::eval::Environment;
+use rust_calculator::numeric::Numeric;
+use rust_calculator::units::Quantity;
+
+use crate::evaluate_in;
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = ". ";
+
+/// File of the home directory keeping the history between sessions.
+const HISTORY_FILE: &str = ".rust_calculator_history";
+
+/// Variable holding the previous result.
+const ANS: &str = "ans";
+
+const HELP: &str = "\
+Enter an expression to evaluate it, `ans` holds the previous result.
+Assign it to a variable with `x = 1` or define a function with `f(x, y) = x + y`,
+statements are separated by `;`.
+Comparisons and `and`, `or`, `not` give 1 or 0, `c ? a : b` chooses a value.
+Lines with unclosed parentheses continue on the next one.
+
+Commands:
+  :help  Show this message
+  :vars  List the variables and functions
+  :quit  Exit, like Ctrl-D
+";
+
+/// What the REPL does after a line.
+#[derive(Debug, PartialEq)]
+enum Step {
+    Continue,
+    Quit,
+}
+
+/// Read and evaluate expressions until the user quits.
+pub fn run<N: Numeric>() -> rustyline::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = dirs::home_dir().map(|home| home.join(HISTORY_FILE));
+    if let Some(history) = &history {
+        // There is no history yet on the first run
+        let _ = editor.load_history(history);
+    }
+
+    let mut env = Environment::<N>::new();
+
+    loop {
+        let input = match read_input(|prompt| editor.readline(prompt)) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input)?;
+
+        if execute(input, &mut env, io::stdout(), io::stderr())? == Step::Quit {
+            break;
+        }
+    }
+
+    if let Some(history) = &history {
+        editor.save_history(history)?;
+    }
+
+    Ok(())
+}
+
+/// Execute the command or evaluate the program `input`, writing its result to `output`
+/// and its errors to `errors`.
+fn execute<N: Numeric>(
+    input: &str,
+    env: &mut Environment<N>,
+    mut output: impl Write,
+    mut errors: impl Write,
+) -> io::Result<Step> {
+    match input {
+        ":quit" => return Ok(Step::Quit),
+        ":help" => write!(output, "{}", HELP)?,
+        ":vars" => {
+            for (name, value) in env.variables() {
+                let value = Quantity::in_si(value.clone(), env.dimension(name));
+                writeln!(output, "{} = {}", name, value)?;
+            }
+            for (name, function) in env.functions() {
+                writeln!(output, "{}{}", name, function)?;
+            }
+        }
+        command if command.starts_with(':') => {
+            writeln!(errors, "error: Unknown command '{}', see :help", command)?
+        }
+        expr => match evaluate_in(expr, env) {
+            Ok(Some(result)) => {
+                writeln!(output, "{}", result)?;
+                env.set_quantity(ANS, result);
+            }
+            Ok(None) => {}
+            Err(e) => write!(errors, "{}", Diagnostic::from_error(expr, &e))?,
+        },
+    }
+
+    Ok(Step::Continue)
+}
+
+/// Read a line with `read_line`, and the following ones while there are unclosed
+/// parentheses.
+fn read_input(
+    mut read_line: impl FnMut(&str) -> rustyline::Result<String>,
+) -> rustyline::Result<String> {
+    let mut input = read_line(PROMPT)?;
+
+    while unclosed_parens(&input) > 0 {
+        input.push(' ');
+        input.push_str(&read_line(CONTINUATION_PROMPT)?);
+    }
+
+    Ok(input)
+}
+
+fn unclosed_parens(input: &str) -> isize {
+    input.chars().fold(0, |depth, c| match c {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_calculator::eval::Environment;
+    use rustyline::error::ReadlineError;
+
+    use crate::repl::{execute, read_input, unclosed_parens, Step, CONTINUATION_PROMPT, PROMPT};
+
+    /// Execute the lines in the same environment, with the output and errors of the last.
+    fn session(lines: &[&str]) -> (Step, String, String) {
+        let mut env = Environment::<f64>::new();
+        let mut result = None;
+        for line in lines {
+            let (mut output, mut errors) = (Vec::new(), Vec::new());
+            let step = execute(line, &mut env, &mut output, &mut errors).unwrap();
+            result = Some((
+                step,
+                String::from_utf8(output).unwrap(),
+                String::from_utf8(errors).unwrap(),
+            ));
+        }
+        result.unwrap()
+    }
+
+    #[test]
+    fn count_unclosed_parens() {
+        assert_eq!(unclosed_parens("1 + 2"), 0);
+        assert_eq!(unclosed_parens("max(1, (2"), 2);
+        assert_eq!(unclosed_parens("(1 + 2) * (3"), 1);
+        assert_eq!(unclosed_parens("1)"), -1);
+    }
+
+    #[test]
+    fn continue_unclosed_lines() {
+        let mut lines = vec!["max(1,", "(2 +", "3))"].into_iter();
+        let mut prompts = Vec::new();
+        let input = read_input(|prompt| {
+            prompts.push(prompt.to_string());
+            Ok(lines.next().unwrap().into())
+        })
+        .unwrap();
+        assert_eq!(input, "max(1, (2 + 3))");
+        assert_eq!(prompts, [PROMPT, CONTINUATION_PROMPT, CONTINUATION_PROMPT]);
+
+        // An extra closing parenthesis is left to the parser
+        let input = read_input(|_| Ok("1)".into())).unwrap();
+        assert_eq!(input, "1)");
+
+        // Ctrl-D in a continuation stops the input
+        let mut lines = vec![Err(ReadlineError::Eof), Ok("(1".to_string())];
+        let result = read_input(|_| lines.pop().unwrap());
+        assert!(matches!(result, Err(ReadlineError::Eof)));
+    }
+
+    #[test]
+    fn execute_lines() {
+        let (step, output, errors) = session(&["x = 2", "f(y) = y * x", "f(3) + 1", "ans * 2"]);
+        assert_eq!(step, Step::Continue);
+        assert_eq!(output, "14\n");
+        assert_eq!(errors, "");
+
+        let (_, output, _) = session(&["x = 2", "f(y) = y * x", ":vars"]);
+        assert_eq!(output, "ans = 2\nx = 2\nf(y) = y * x\n");
+
+        let (_, output, errors) = session(&["1 +"]);
+        assert_eq!(output, "");
+        assert!(errors.contains("error"));
+    }
+
+    #[test]
+    fn execute_commands() {
+        assert_eq!(session(&[":quit"]).0, Step::Quit);
+        assert!(session(&[":help"]).1.contains(":vars"));
+
+        let (step, _, errors) = session(&[":what"]);
+        assert_eq!(step, Step::Continue);
+        assert_eq!(errors, "error: Unknown command ':what', see :help\n");
+    }
+}
@@ -0,0 +1,494 @@
+//! # Simplify module
+//! Contains a rewrite engine simplifying an [Expr] from the leaves up.
+//!
+//! - Subtrees made only of numbers are folded, unless their evaluation fails.
+//! - `+x` becomes `x` and `--x` becomes `x`.
+//! - Sums are flattened and their like terms collected, so `x + 0` becomes `x` and
+//!   `2x - x + 1 - 1` becomes `x`.
+//! - Products are flattened and the powers of a same base collected, so `x * 1`
+//!   becomes `x`, `0 * x` becomes `0` and `x * x / x` becomes `x`.
+//! - The terms of sums and the factors of products are sorted, so `x*y + y*x` becomes
+//!   `2 * x * y`.
+//! - `x ** 1` becomes `x` and `x ** 0` becomes `1`.
+//! - `p%` becomes `p / 100` and `x + p%` becomes `x * (100 + p) / 100`.
+//! - Conditionals with a constant condition become the branch it chooses, so
+//!   `1 < 2 ? x : y` becomes `x`.
+//!
+//! The constants `e`, `pi` and `i` are kept symbolic, as are the function calls that
+//! don't fold to an integer like `ln(2)`. The rules assume the divisors
+//! are not zero, `x / x` becomes `1`.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::simplify::simplify;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("2x * 3 + --x**1 - 0 * y + (1 + 2)").unwrap())
+//!     .parse()
+//!     .unwrap();
+//!
+//! assert_eq!(simplify(&expr).to_string(), "7 * x + 3");
+//! # }
+//! ```
+
+use crate::ast::{BinOpType, Expr, UnaryOpType};
+use crate::eval::{relative_percentage, Eval};
+use crate::numeric::Numeric;
+
+/// Simplified copy of `expr`, evaluating to the same value where both are defined.
+pub fn simplify<N: Numeric>(expr: &Expr<N>) -> Expr<N> {
+    match expr {
+        Expr::Number(_) | Expr::Quantity(..) | Expr::Variable(_) | Expr::E | Expr::Pi | Expr::I => {
+            expr.clone()
+        }
+        // The target is a unit, kept as written
+        Expr::Convert(value, target) => Expr::Convert(simplify(value).boxed(), target.clone()),
+        Expr::UnaryOp(UnaryOpType::Noop, operand) => simplify(operand),
+        Expr::UnaryOp(UnaryOpType::Negate, operand) => negate(simplify(operand)),
+        // The percentages are divided so that their meaning doesn't depend on their place
+        Expr::UnaryOp(UnaryOpType::Percent, operand) => simplify(&Expr::BinOp(
+            operand.clone(),
+            BinOpType::Div,
+            number(100.).boxed(),
+        )),
+        Expr::UnaryOp(op @ (UnaryOpType::Not | UnaryOpType::Factorial), operand) => {
+            let unary = Expr::UnaryOp(*op, simplify(operand).boxed());
+            match &unary {
+                Expr::UnaryOp(_, operand) if constant(operand).is_some() => fold(unary),
+                _ => unary,
+            }
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            let condition = simplify(condition);
+            match constant(&condition) {
+                Some(value) if value.is_zero() => simplify(otherwise),
+                Some(_) => simplify(then),
+                None => Expr::Conditional(
+                    condition.boxed(),
+                    simplify(then).boxed(),
+                    simplify(otherwise).boxed(),
+                ),
+            }
+        }
+        Expr::Call(name, args) => {
+            let args = args.iter().map(simplify).collect::<Vec<_>>();
+            let call = Expr::Call(name.clone(), args.clone());
+            // Like `pi`, irrational values such as `ln(2)` are kept in their exact form
+            match call.try_eval() {
+                Ok(value)
+                    if args.iter().all(|arg| constant(arg).is_some()) && value.is_integer() =>
+                {
+                    fold(call)
+                }
+                _ => call,
+            }
+        }
+        Expr::BinOp(left, op @ (BinOpType::Add | BinOpType::Sub), right)
+            if right.percentage().is_some() =>
+        {
+            simplify(&relative_percentage(left, *op, right.percentage().unwrap()))
+        }
+        Expr::BinOp(left, op, right) => {
+            let (left, right) = (simplify(left), simplify(right));
+
+            match op {
+                BinOpType::Add | BinOpType::Sub => {
+                    let mut sum = Sum::default();
+                    sum.collect(left, true);
+                    sum.collect(right, *op == BinOpType::Add);
+                    sum.build()
+                }
+                BinOpType::Mul | BinOpType::Div => {
+                    let mut product = Product::default();
+                    let collected = product
+                        .collect(left.clone(), false)
+                        .and_then(|_| product.collect(right.clone(), *op == BinOpType::Div));
+
+                    match collected {
+                        Some(()) => product.build(),
+                        // Division by zero, leave it to the evaluation
+                        None => binary(left, *op, right),
+                    }
+                }
+                BinOpType::Pow => power(left, right),
+                _ if constant(&left).is_some() && constant(&right).is_some() => {
+                    fold(binary(left, *op, right))
+                }
+                _ => binary(left, *op, right),
+            }
+        }
+    }
+}
+
+/// Terms of a sum with their coefficient, `None` is the constant term.
+struct Sum<N> {
+    terms: Vec<(N, Option<Expr<N>>)>,
+}
+
+impl<N> Default for Sum<N> {
+    fn default() -> Self {
+        Self { terms: Vec::new() }
+    }
+}
+
+impl<N: Numeric> Sum<N> {
+    /// Add the terms of a simplified `expr`, or subtract them if not `positive`.
+    fn collect(&mut self, expr: Expr<N>, positive: bool) {
+        match expr {
+            Expr::BinOp(left, BinOpType::Add, right) => {
+                self.collect(*left, positive);
+                self.collect(*right, positive);
+            }
+            Expr::BinOp(left, BinOpType::Sub, right) => {
+                self.collect(*left, positive);
+                self.collect(*right, !positive);
+            }
+            Expr::UnaryOp(UnaryOpType::Negate, operand) => self.collect(*operand, !positive),
+            expr => {
+                let (coefficient, term) = split_coefficient(expr);
+                let coefficient = if positive { coefficient } else { -coefficient };
+
+                match self.terms.iter_mut().find(|(_, other)| *other == term) {
+                    Some((sum, _)) => *sum = sum.clone() + coefficient,
+                    None => self.terms.push((coefficient, term)),
+                }
+            }
+        }
+    }
+
+    fn build(mut self) -> Expr<N> {
+        // In a canonical order so that equal sums are built the same, the constant last
+        self.terms
+            .sort_by_cached_key(|(_, term)| (term.is_none(), term.as_ref().map(canonical_key)));
+
+        let mut result = None;
+        for (coefficient, term) in self.terms {
+            if coefficient.is_zero() {
+                continue;
+            }
+
+            let negative = coefficient.is_negative();
+            let magnitude = if negative { -coefficient } else { coefficient };
+            let term = match term {
+                Some(term) => {
+                    let mut product = Product {
+                        numer: magnitude.clone(),
+                        ..Product::default()
+                    };
+                    match product.collect(term.clone(), false) {
+                        Some(()) => product.build(),
+                        None if magnitude == N::from_f64(1.) => term,
+                        None => binary(Expr::Number(magnitude), BinOpType::Mul, term),
+                    }
+                }
+                None => Expr::Number(magnitude),
+            };
+
+            result = Some(match result {
+                None if negative => Expr::UnaryOp(UnaryOpType::Negate, term.boxed()),
+                None => term,
+                Some(sum) if negative => binary(sum, BinOpType::Sub, term),
+                Some(sum) => binary(sum, BinOpType::Add, term),
+            });
+        }
+
+        result.unwrap_or_else(|| number(0.))
+    }
+}
+
+/// Split a simplified term into its numeric coefficient and the rest of its factors.
+fn split_coefficient<N: Numeric>(expr: Expr<N>) -> (N, Option<Expr<N>>) {
+    let mut product = Product::default();
+    if product.collect(expr.clone(), false).is_none() {
+        return (N::from_f64(1.), Some(expr));
+    }
+
+    if product.factors.is_empty() {
+        (product.numer / product.denom, None)
+    } else {
+        let coefficient = std::mem::replace(&mut product.numer, N::from_f64(1.));
+        (coefficient, Some(product.build()))
+    }
+}
+
+/// A product `numer / denom * base1 ** exponent1 * base2 ** exponent2 ...`.
+struct Product<N> {
+    numer: N,
+    denom: N,
+    factors: Vec<(Expr<N>, Expr<N>)>,
+}
+
+impl<N: Numeric> Default for Product<N> {
+    fn default() -> Self {
+        Self {
+            numer: N::from_f64(1.),
+            denom: N::from_f64(1.),
+            factors: Vec::new(),
+        }
+    }
+}
+
+impl<N: Numeric> Product<N> {
+    /// Multiply by the factors of a simplified `expr`, or divide by them if `inverted`.
+    /// `None` if it divides by zero.
+    fn collect(&mut self, expr: Expr<N>, inverted: bool) -> Option<()> {
+        match expr {
+            Expr::BinOp(left, BinOpType::Mul, right) => {
+                self.collect(*left, inverted)?;
+                self.collect(*right, inverted)
+            }
+            Expr::BinOp(left, BinOpType::Div, right) => {
+                self.collect(*left, inverted)?;
+                self.collect(*right, !inverted)
+            }
+            Expr::UnaryOp(UnaryOpType::Negate, operand) => {
+                self.numer = -self.numer.clone();
+                self.collect(*operand, inverted)
+            }
+            expr => {
+                match constant(&expr) {
+                    Some(value) if inverted && value.is_zero() => return None,
+                    Some(value) if inverted => self.denom = self.denom.clone() * value,
+                    Some(value) => self.numer = self.numer.clone() * value,
+                    None => {
+                        let (base, exponent) = match expr {
+                            Expr::BinOp(base, BinOpType::Pow, exponent) => (*base, *exponent),
+                            expr => (expr, number(1.)),
+                        };
+                        let exponent = if inverted { negate(exponent) } else { exponent };
+
+                        match self.factors.iter_mut().find(|(other, _)| *other == base) {
+                            Some((_, sum)) => *sum = add(sum.clone(), exponent),
+                            None => self.factors.push((base, exponent)),
+                        }
+                    }
+                }
+                Some(())
+            }
+        }
+    }
+
+    fn build(self) -> Expr<N> {
+        let Self {
+            mut numer,
+            mut denom,
+            mut factors,
+        } = self;
+        // In a canonical order so that equal products are built the same
+        factors.sort_by_cached_key(|(base, _)| canonical_key(base));
+
+        if numer.is_zero() {
+            return number(0.);
+        }
+        if denom.is_negative() {
+            numer = -numer;
+            denom = -denom;
+        }
+        if factors.is_empty() || (numer.clone() / denom.clone()).is_integer() {
+            numer = numer / denom;
+            denom = N::from_f64(1.);
+        }
+
+        let negative = numer.is_negative();
+        if negative {
+            numer = -numer;
+        }
+
+        let one = N::from_f64(1.);
+        let mut top = Vec::new();
+        let mut bottom = Vec::new();
+        if numer != one || factors.is_empty() {
+            top.push(Expr::Number(numer));
+        }
+        if denom != one {
+            bottom.push(Expr::Number(denom));
+        }
+
+        for (base, exponent) in factors {
+            if is_negative(&exponent) {
+                bottom.push(power(base, negate(exponent)));
+            } else if !is_number(&exponent, 0.) {
+                top.push(power(base, exponent));
+            }
+        }
+
+        // `a * b / (c * d)`, the division is left associative like the product
+        let top = chain(top).unwrap_or_else(|| number(1.));
+        let result = match chain(bottom) {
+            Some(bottom) => binary(top, BinOpType::Div, bottom),
+            None => top,
+        };
+
+        if negative {
+            Expr::UnaryOp(UnaryOpType::Negate, result.boxed())
+        } else {
+            result
+        }
+    }
+}
+
+/// Key sorting the terms and factors in a canonical order: the atoms, then the calls and
+/// then the operations, each in the order of their text.
+fn canonical_key<N: Numeric>(expr: &Expr<N>) -> (u8, String) {
+    let rank = match expr {
+        Expr::Call(..) => 1,
+        Expr::BinOp(..) | Expr::UnaryOp(..) | Expr::Convert(..) | Expr::Conditional(..) => 2,
+        _ => 0,
+    };
+    (rank, expr.to_string())
+}
+
+/// Product of `factors` from left to right.
+fn chain<N>(factors: Vec<Expr<N>>) -> Option<Expr<N>> {
+    factors
+        .into_iter()
+        .reduce(|left, right| binary(left, BinOpType::Mul, right))
+}
+
+fn add<N: Numeric>(left: Expr<N>, right: Expr<N>) -> Expr<N> {
+    let mut sum = Sum::default();
+    sum.collect(left, true);
+    sum.collect(right, true);
+    sum.build()
+}
+
+fn negate<N: Numeric>(expr: Expr<N>) -> Expr<N> {
+    let mut sum = Sum::default();
+    sum.collect(expr, false);
+    sum.build()
+}
+
+fn power<N: Numeric>(base: Expr<N>, exponent: Expr<N>) -> Expr<N> {
+    if is_number(&exponent, 0.) || is_number(&base, 1.) {
+        number(1.)
+    } else if is_number(&exponent, 1.) {
+        base
+    } else if constant(&base).is_some() && constant(&exponent).is_some() {
+        fold(binary(base, BinOpType::Pow, exponent))
+    } else {
+        binary(base, BinOpType::Pow, exponent)
+    }
+}
+
+/// Evaluate `expr` made only of numbers, or leave it as is if that fails.
+fn fold<N: Numeric>(expr: Expr<N>) -> Expr<N> {
+    match expr.try_eval() {
+        Ok(value) if value.is_negative() => {
+            Expr::UnaryOp(UnaryOpType::Negate, Expr::Number(-value).boxed())
+        }
+        Ok(value) => Expr::Number(value),
+        Err(_) => expr,
+    }
+}
+
+/// Value of a number, possibly negated as the parser never reads negative numbers.
+fn constant<N: Numeric>(expr: &Expr<N>) -> Option<N> {
+    match expr {
+        Expr::Number(num) => Some(num.clone()),
+        Expr::UnaryOp(UnaryOpType::Negate, operand) => constant(operand).map(|num| -num),
+        Expr::UnaryOp(UnaryOpType::Noop, operand) => constant(operand),
+        _ => None,
+    }
+}
+
+fn is_number<N: Numeric>(expr: &Expr<N>, value: f64) -> bool {
+    constant(expr) == Some(N::from_f64(value))
+}
+
+/// Whether a simplified `expr` is written with a leading minus.
+fn is_negative<N: Numeric>(expr: &Expr<N>) -> bool {
+    matches!(expr, Expr::UnaryOp(UnaryOpType::Negate, _))
+}
+
+fn number<N: Numeric>(value: f64) -> Expr<N> {
+    Expr::Number(N::from_f64(value))
+}
+
+fn binary<N>(left: Expr<N>, op: BinOpType, right: Expr<N>) -> Expr<N> {
+    Expr::BinOp(left.boxed(), op, right.boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use crate::simplify::simplify;
+    use crate::token::tokenize;
+
+    fn simplified(source: &str) -> String {
+        let expr = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+        simplify(&expr).to_string()
+    }
+
+    #[test]
+    fn fold_constants() {
+        assert_eq!(simplified("1 + 2 * 3"), "7");
+        assert_eq!(simplified("2 ** 10 - 24"), "1000");
+        assert_eq!(simplified("1 - 3"), "-2");
+        assert_eq!(simplified("sqrt(16) * x"), "4 * x");
+        assert_eq!(simplified("1 / 4"), "0.25");
+        assert_eq!(simplified("pi * 2"), "2 * pi");
+        assert_eq!(simplified("ln(2) * 2"), "2 * ln(2)");
+        assert_eq!(simplified("1 + 1 == 2 and not 0"), "1");
+        assert_eq!(simplified("200 * 15% + 3! + 7 % 4"), "39");
+        assert_eq!(simplified("x + 10%"), "110 * x / 100");
+        assert_eq!(simplified("x% + x!"), "x / 100 + x!");
+        assert_eq!(simplified("1 < 2 ? x + 0 : y"), "x");
+        assert_eq!(simplified("x < 2 ? x + 0 : y"), "x < 2 ? x : y");
+        // Failing evaluations are left as is
+        assert_eq!(simplified("sqrt(-1) + 1 / 0"), "sqrt(-1) + 1 / 0");
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(simplified("+x"), "x");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("-(-(-x))"), "-x");
+        assert_eq!(simplified("x * 1 + 0"), "x");
+        assert_eq!(simplified("0 * x + y ** 1"), "y");
+        assert_eq!(simplified("x ** 0"), "1");
+        assert_eq!(simplified("0 - x"), "-x");
+        assert_eq!(simplified("x / 1 / 1"), "x");
+        assert_eq!(simplified("-(x + y)"), "-x - y");
+    }
+
+    #[test]
+    fn collect_like_terms() {
+        assert_eq!(simplified("2x + 3x"), "5 * x");
+        assert_eq!(simplified("x + y - x"), "y");
+        assert_eq!(simplified("x - 2x"), "-x");
+        assert_eq!(simplified("x*y + 2*x*y + 1 - 1"), "3 * x * y");
+        assert_eq!(simplified("x / 3 + x / 3"), "2 * x / 3");
+        assert_eq!(simplified("sin(x) + 2 sin(x)"), "3 * sin(x)");
+        // The factors and terms are compared whatever their order
+        assert_eq!(simplified("x*y + y*x"), "2 * x * y");
+        assert_eq!(simplified("(x + y) * (y + x)"), "(x + y) ** 2");
+        assert_eq!(simplified("y*x*z - z*x*y + 1"), "1");
+    }
+
+    #[test]
+    fn collect_powers() {
+        assert_eq!(simplified("x * x"), "x ** 2");
+        assert_eq!(simplified("x * x ** 2 / x"), "x ** 2");
+        assert_eq!(simplified("x / x"), "1");
+        assert_eq!(simplified("2 * x * 3 / y"), "6 * x / y");
+        assert_eq!(simplified("x ** y * x ** z"), "x ** (y + z)");
+        assert_eq!(simplified("-x * -y"), "x * y");
+        assert_eq!(simplified("x / -2"), "-(x / 2)");
+    }
+
+    #[test]
+    fn stable_and_round_trip() {
+        let sources = [
+            "2x + 3y - x / 4 + sin(x) ** 2 * 2",
+            "(x + 1) * (x - 1) / (x * y)",
+            "x ** -2 + e ** x * pi",
+            "-(x ** 2) - 3 * x / y",
+        ];
+
+        for source in sources.iter() {
+            let once = simplified(source);
+            assert_eq!(simplified(&once), once, "{} is not stable", once);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! # Span module
+//! Contains the types used to locate tokens and errors in the source string.
+
+use std::fmt::{Display, Formatter};
+
+/// A range of byte offsets `[start, end)` in the source string.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Span covering a single char starting at the given offset.
+    pub fn of_char(start: usize, c: char) -> Self {
+        Self::new(start, start + c.len_utf8())
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Self {
+        Self::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A value paired with the [Span] it was read from.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
@@ -1,14 +1,25 @@
 //! # Tokenizer module
-//! Contains the logic to transform a [String] into a [Vec] of [Token]s.
+//! Contains the [Lexer] iterating over the [Token]s of a string, and [tokenize] to
+//! collect them.
+//!
+//! Every [Token] is paired with the [Span] of the source it was read from.
+//!
+//! Numbers are decimal, like `12.5`, or integers written in another base after the
+//! `0x`, `0o` or `0b` prefix. A `_` between two digits is a separator, as in `1_000`,
+//! anywhere else in a number it is an error.
+//! Decimal numbers may have an exponent introduced by `e` or `E` when digits follow it,
+//! directly or after a sign: `2e3` and `2E+3` are `2000`. Otherwise a lowercase `e` is
+//! the constant, so `2e` and `2e + 3` multiply by it, while `2E` multiplies by the
+//! variable `E`. In the same way, `0x` alone is `0 * x`.
 //!
 //! ## Example
 //! ```rust
 //! # fn main() {
 //! # use rust_calculator::token::{tokenize, Token};
-//! let tokens = tokenize("1+2*3".into()).unwrap();
+//! let tokens = tokenize("1+2*3").unwrap();
 //!
 //! assert_eq!(
-//!     tokens,
+//!     tokens.into_iter().map(|t| t.value).collect::<Vec<_>>(),
 //!     vec![
 //!         Token::Number(1.),
 //!         Token::Plus,
@@ -21,13 +32,26 @@
 //! ```
 
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
+
+use num_bigint::BigInt;
+use num_traits::Num;
 
+use crate::ast::Number;
 use crate::errors::{ParserError, Result};
+use crate::numeric::Numeric;
+use crate::span::{Span, Spanned};
+use crate::units::unit;
 use std::fmt::{Debug, Display, Formatter};
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-pub enum Token {
+/// A token of the source, numbers are read as `N`, see [Numeric].
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Token<N = Number> {
     Plus,
     Minus,
     UnaryPlus,
@@ -37,72 +61,123 @@ pub enum Token {
     Slash,
     ParenStart,
     ParenEnd,
+    Comma,
     E,
     Pi,
-    Number(f32),
-
-    Ignore,
+    I,
+    Number(N),
+    /// A number annotated with a unit, like `3 km`.
+    Quantity(N, String),
+    Ident(String),
+    /// The conversion to a unit, `in` or `to`.
+    In,
+    /// The `=` of an assignment.
+    Equal,
+    /// The end of a statement, `;` or a newline.
+    Separator,
+    EqualEqual,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Not,
+    /// The `?` of a conditional, `c ? a : b`.
+    Question,
+    /// The `:` of a conditional.
+    Colon,
+    /// The modulo, `%` followed by an operand.
+    Percent,
+    SlashSlash,
+    /// A percentage, `%` not followed by an operand.
+    PostfixPercent,
+    /// The factorial.
+    Bang,
 }
 
-impl Display for Token {
+impl<N: Debug> Display for Token<N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         Debug::fmt(self, f)
     }
 }
 
 // Meaning of these tokens
-impl Token {
+impl<N> Token<N> {
     pub fn is_atom(&self) -> bool {
-        match self {
-            Self::E | Self::Pi | Self::Number(_) => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Self::E | Self::Pi | Self::I | Self::Number(_) | Self::Quantity(..) | Self::Ident(_)
+        )
     }
 
+    /// The conditional is not an operator, its `?` and `:` are handled by the parser.
     pub fn is_op(&self) -> bool {
-        match self {
-            Self::Plus
-            | Self::Minus
-            | Self::UnaryPlus
-            | Self::UnaryMinus
-            | Self::Times
-            | Self::Slash
-            | Self::TimesTimes => true,
-            _ => false,
-        }
+        self.is_bin_op() || self.is_unary_op()
+    }
+
+    /// Whether the operator follows its operand, like `!` in `3!`.
+    pub fn is_postfix_op(&self) -> bool {
+        matches!(self, Self::PostfixPercent | Self::Bang)
     }
 
     /// Assumes [Token#is_op] returned true.
     pub fn is_bin_op(&self) -> bool {
-        match self {
-            Self::Plus | Self::Minus | Self::Times | Self::Slash | Self::TimesTimes => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Self::Plus
+                | Self::Minus
+                | Self::Times
+                | Self::Slash
+                | Self::Percent
+                | Self::SlashSlash
+                | Self::TimesTimes
+                | Self::In
+                | Self::EqualEqual
+                | Self::NotEqual
+                | Self::Less
+                | Self::LessEqual
+                | Self::Greater
+                | Self::GreaterEqual
+                | Self::And
+                | Self::Or
+        )
     }
 
     /// Assumes [Token#is_op] returned true.
     pub fn is_unary_op(&self) -> bool {
-        match self {
-            Self::UnaryPlus | Self::UnaryMinus => true,
-            _ => false,
-        }
+        matches!(self, Self::UnaryPlus | Self::UnaryMinus | Self::Not)
     }
 
     /// Assumes [Token#is_op] returned true.
     pub fn is_left_assoc(&self) -> bool {
-        match self {
-            Self::Plus | Self::Minus | Self::Times => true,
-            _ => false,
-        }
+        !matches!(
+            self,
+            Self::TimesTimes | Self::UnaryPlus | Self::UnaryMinus | Self::Not
+        )
     }
 
-    /// Assumes [Token#is_op] returned true.
+    /// Assumes [Token#is_op] or [Token#is_postfix_op] returned true, or that it is a part
+    /// of a conditional.
     pub fn op_prec(&self) -> u32 {
         match self {
-            Self::Plus | Self::Minus => 1,
-            Self::Times | Self::Slash => 2,
-            Self::TimesTimes => 3,
-            Self::UnaryPlus | Self::UnaryMinus => 4,
+            Self::In => 1,
+            Self::Question | Self::Colon => 2,
+            Self::Or => 3,
+            Self::And => 4,
+            Self::Not => 5,
+            Self::EqualEqual
+            | Self::NotEqual
+            | Self::Less
+            | Self::LessEqual
+            | Self::Greater
+            | Self::GreaterEqual => 6,
+            Self::Plus | Self::Minus => 7,
+            Self::Times | Self::Slash | Self::Percent | Self::SlashSlash => 8,
+            Self::TimesTimes => 9,
+            Self::UnaryPlus | Self::UnaryMinus => 10,
+            Self::PostfixPercent | Self::Bang => 11,
             _ => 0,
         }
     }
@@ -110,186 +185,556 @@ impl Token {
     pub fn is_before_unary(&self) -> bool {
         match self {
             t if t.is_op() => true,
-            Self::ParenStart => true,
+            Self::ParenStart | Self::Comma => true,
             _ => false,
         }
     }
 
     pub fn is_paren(&self) -> bool {
-        match self {
-            Self::ParenStart | Self::ParenEnd => true,
-            _ => false,
-        }
+        matches!(self, Self::ParenStart | Self::ParenEnd)
     }
 }
 
 /// [Token]ize the given input string.
-pub fn tokenize(source: String) -> Result<Vec<Token>> {
-    let mut tokens = Vec::<Token>::new();
+pub fn tokenize(source: &str) -> Result<Vec<Spanned<Token>>> {
+    tokenize_as(source)
+}
+
+/// [Token]ize the given input string, reading the numbers as `N`.
+pub fn tokenize_as<N: Numeric>(source: &str) -> Result<Vec<Spanned<Token<N>>>> {
+    Lexer::new(source).collect()
+}
 
-    let mut iterator = source.chars().into_iter().peekable();
-    while let Some(c) = iterator.next() {
+/// An iterator over the [Token]s of a string, reading the numbers as `N`.
+///
+/// Implicit multiplications are inserted between atomic parts, like in `1(`, `)1`,
+/// `)(` or `2pi`. An identifier directly followed by `(` is a function call, so `f(` is
+/// left untouched. The inserted [Token::Times] spans the gap between its neighbours.
+///
+/// Nothing is produced after an error.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a, N = Number> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    previous: Previous,
+    /// End of the previous token.
+    end: usize,
+    /// Token read after an implicit multiplication, produced next.
+    pending: Option<Spanned<Token<N>>>,
+    /// Number of unclosed `(`, newlines inside parentheses are spaces.
+    depth: usize,
+    /// Whether a token was produced since the last separator, so a newline ends a
+    /// statement.
+    in_statement: bool,
+    failed: bool,
+}
+
+/// What the previous token allows to follow it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Previous {
+    /// Nothing, an operator, `(` or `,`: a sign is a unary operator.
+    Operator,
+    /// A number, a constant, `)` or a postfix operator: multiplied by a following operand
+    /// or `(`.
+    Operand,
+    /// An identifier: multiplied by a following operand, called by a following `(`.
+    Ident,
+}
+
+impl<'a, N: Numeric> Lexer<'a, N> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+            previous: Previous::Operator,
+            end: 0,
+            pending: None,
+            depth: 0,
+            in_statement: false,
+            failed: false,
+        }
+    }
+
+    /// Read the next token of the source, without the implicit multiplications.
+    fn read(&mut self) -> Option<Result<Spanned<Token<N>>>> {
+        // Newlines separate the statements, not the lines of an expression in parentheses
+        let separates = self.depth == 0 && self.in_statement;
+        let (start, c) = self
+            .chars
+            .find(|&(_, c)| !c.is_whitespace() || (c == '\n' && separates))?;
+
+        let mut span = Span::of_char(start, c);
         let token = match c {
-            '+' => {
-                if let Some(prev) = tokens.last() {
-                    if prev.is_before_unary() {
-                        Token::UnaryPlus
-                    } else {
-                        Token::Plus
-                    }
-                } else {
-                    Token::UnaryPlus
-                }
+            '+' if self.previous == Previous::Operator => Token::UnaryPlus,
+            '+' => Token::Plus,
+            '-' if self.previous == Previous::Operator => Token::UnaryMinus,
+            '-' => Token::Minus,
+            '*' if self.next_is('*') => {
+                span.end += 1;
+                Token::TimesTimes
             }
-            '-' => {
-                if let Some(prev) = tokens.last() {
-                    if prev.is_before_unary() {
-                        Token::UnaryMinus
-                    } else {
-                        Token::Minus
-                    }
-                } else {
-                    Token::UnaryMinus
-                }
-            }
-            '*' => {
-                if let Some('*') = iterator.peek() {
-                    // Can safely unwrap
-                    iterator.next().unwrap();
-                    Token::TimesTimes
-                } else {
-                    Token::Times
-                }
+            '*' => Token::Times,
+            '/' if self.next_is('/') => {
+                span.end += 1;
+                Token::SlashSlash
             }
             '/' => Token::Slash,
-            '(' => Token::ParenStart,
-            ')' => Token::ParenEnd,
-            'e' => Token::E,
-            // Parse PI
-            'p' => {
-                if let Some('i') = iterator.peek() {
-                    // Can safely unwrap
-                    iterator.next();
-                    Token::Pi
-                } else {
-                    return Err(ParserError::Tokenize("Expected token 'pi'".into()))?;
-                }
+            '%' if self.operand_after(start + 1) => Token::Percent,
+            '%' => Token::PostfixPercent,
+            '(' => {
+                self.depth += 1;
+                Token::ParenStart
+            }
+            ')' => {
+                self.depth = self.depth.saturating_sub(1);
+                Token::ParenEnd
+            }
+            ',' => Token::Comma,
+            '=' if self.next_is('=') => {
+                span.end += 1;
+                Token::EqualEqual
+            }
+            '=' => Token::Equal,
+            '!' if self.next_is('=') => {
+                span.end += 1;
+                Token::NotEqual
+            }
+            '!' => Token::Bang,
+            '<' if self.next_is('=') => {
+                span.end += 1;
+                Token::LessEqual
+            }
+            '<' => Token::Less,
+            '>' if self.next_is('=') => {
+                span.end += 1;
+                Token::GreaterEqual
             }
-            digit @ '0'..='9' => tokenize_number(&mut iterator, digit)?,
-            '.' => tokenize_number(&mut iterator, '.')?,
-            c if c.is_whitespace() => Token::Ignore,
-            _ => {
-                return Err(ParserError::Tokenize(format!("Unexpected token '{}'", c)))?;
+            '>' => Token::Greater,
+            '?' => Token::Question,
+            ':' => Token::Colon,
+            ';' | '\n' => Token::Separator,
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                span.end = self.skip_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                self.read_word(span)
             }
+            '0'..='9' | '.' => match self.read_number(start) {
+                Ok((Token::Number(num), end)) => match self.unit_after(end) {
+                    Some((unit, end)) => {
+                        self.advance_to(end);
+                        span.end = end;
+                        Token::Quantity(num, unit)
+                    }
+                    None => {
+                        span.end = end;
+                        Token::Number(num)
+                    }
+                },
+                Ok((token, end)) => {
+                    span.end = end;
+                    token
+                }
+                Err(e) => return Some(Err(e)),
+            },
+            _ => return Some(Err(ParserError::UnexpectedChar(c, span))),
         };
 
-        if token != Token::Ignore {
-            tokens.push(token)
+        Some(Ok(Spanned::new(token, span)))
+    }
+
+    /// Skip the next char if it is `c`.
+    fn next_is(&mut self, c: char) -> bool {
+        self.chars.next_if(|&(_, next)| next == c).is_some()
+    }
+
+    /// Skip the chars matching `predicate`, returns the offset of the first other one.
+    fn skip_while(&mut self, predicate: impl Fn(char) -> bool) -> usize {
+        while let Some(&(end, c)) = self.chars.peek() {
+            if !predicate(c) {
+                return end;
+            }
+            self.chars.next();
         }
+
+        self.source.len()
     }
 
-    // Expand implicit mul
-    tokens = expand_implicit_mul(tokens);
+    /// Read a single number according to the `<number>` rule of the grammar, returns it
+    /// with the offset of its end.
+    fn read_number(&mut self, start: usize) -> Result<(Token<N>, usize)> {
+        let bytes = self.source.as_bytes();
 
-    Ok(tokens)
-}
+        let radix = match &bytes[start..] {
+            [b'0', b'x', ..] => 16,
+            [b'0', b'o', ..] => 8,
+            [b'0', b'b', ..] => 2,
+            _ => 10,
+        };
+        // Only a prefix if a digit or a misplaced separator follows, `0x` alone is `0 * x`
+        if radix != 10
+            && (is_digit(bytes.get(start + 2), radix) || bytes.get(start + 2) == Some(&b'_'))
+        {
+            let end = skip_digits(bytes, start + 2, radix)?;
+            self.advance_to(end);
+
+            // The digits are valid, convert them to decimal to keep big integers exact
+            let digits = self.source[start + 2..end].replace('_', "");
+            let value = BigInt::from_str_radix(&digits, radix).unwrap();
+            return Ok((Token::Number(N::from_literal(&value.to_string())), end));
+        }
 
-/// Tokenize a single number according to the following grammar:
-/// ```bnf
-/// <number>  ::= <digits> [ "." [ <digits> ] ] | "." <digits>
-/// <digits>  ::= "0" .. "9"
-/// ```
-fn tokenize_number(iterator: &mut Peekable<Chars>, first_digit: char) -> Result<Token> {
-    let mut acc = String::new();
-    acc.push(first_digit);
-
-    // Read the integer part of the number
-    // (or the decimal part if the first_digit was a dot)
-    while let Some(digit @ '0'..='9') = iterator.peek() {
-        acc.push(*digit);
-        iterator.next();
-    }
-
-    // Sanity check, a single dot is not a valid number
-    if first_digit == '.' && acc.len() == 1 {
-        return Err(ParserError::Tokenize(
-            "A single dot isn't a valid number !".into(),
-        ))?;
-    }
-
-    // If the first char was a dot, we were reading the decimal part already, so skip this step.
-    if first_digit != '.' {
-        if let Some('.') = iterator.peek() {
-            acc.push('.');
-            iterator.next();
-
-            while let Some(digit @ '0'..='9') = iterator.peek() {
-                acc.push(*digit);
-                iterator.next();
+        // Read the integer part of the number, empty if the first char is a dot
+        let mut end = skip_digits(bytes, start, 10)?;
+        if bytes.get(end) == Some(&b'.') {
+            let decimals = skip_digits(bytes, end + 1, 10)?;
+
+            // Sanity check, a single dot is not a valid number
+            if end == start && decimals == end + 1 {
+                return Err(ParserError::LoneDot(Span::of_char(start, '.')));
             }
+            end = decimals;
         }
+
+        // Only an exponent if a digit follows, `2e` is `2 * e` and `2E` is `2 * E`
+        if let Some(b'e') | Some(b'E') = bytes.get(end) {
+            let sign = matches!(bytes.get(end + 1), Some(b'+') | Some(b'-')) as usize;
+            if is_digit(bytes.get(end + 1 + sign), 10) {
+                end = skip_digits(bytes, end + 1 + sign, 10)?;
+            }
+        }
+
+        self.advance_to(end);
+        let literal = &self.source[start..end];
+        let value = if literal.contains('_') {
+            N::from_literal(&literal.replace('_', ""))
+        } else {
+            N::from_literal(literal)
+        };
+
+        Ok((Token::Number(value), end))
     }
 
-    // We can safely unwrap because we are good at parsing
-    let number = acc.parse::<f32>().unwrap();
-    Ok(Token::Number(number))
-}
+    /// The unit annotating the number ending at `end`, with the offset of its end. The
+    /// name of a function called like in `2 min(x, y)` is not a unit. An integer power
+    /// applies to the unit alone: `3 m**2` is 3 square meters, and units joined by `*` or
+    /// `/` without spaces are a single one, like `km/h`.
+    fn unit_after(&self, end: usize) -> Option<(String, usize)> {
+        let (mut name, mut end) = self.unit_power_at(self.skip_spaces(end))?;
+
+        while let Some(op) = self.source[end..].chars().next() {
+            if !matches!(op, '*' | '/') || self.source[end..].starts_with("**") {
+                break;
+            }
+            match self.unit_power_at(end + 1) {
+                Some((unit, unit_end)) => {
+                    name.push(op);
+                    name.push_str(&unit);
+                    end = unit_end;
+                }
+                None => break,
+            }
+        }
+
+        Some((name, end))
+    }
+
+    /// The unit starting at the offset `start` with its integer power if any, and the
+    /// offset of its end.
+    fn unit_power_at(&self, start: usize) -> Option<(String, usize)> {
+        let source = self.source;
+        let end = source[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .map_or(source.len(), |len| start + len);
+
+        let word = &source[start..end];
+        let called = source[self.skip_spaces(end)..].starts_with('(');
+        if unit(word).is_none() || called {
+            return None;
+        }
 
-/// Insert implicit multiplications between atomic parts.
-/// Example of when an implicit mul will be inserted.
-/// `1(`, `)1`, `)(`, `2pi`
-fn expand_implicit_mul(mut tokens: Vec<Token>) -> Vec<Token> {
-    let mut insert_indices = Vec::new();
+        match self.power_after(end) {
+            Some((exponent, end)) => Some((format!("{}**{}", word, exponent), end)),
+            None => Some((word.into(), end)),
+        }
+    }
+
+    /// The integer exponent of a `**` starting at the offset `start`, with the offset of
+    /// its end. `None` if the exponent is followed by anything binding tighter.
+    fn power_after(&self, start: usize) -> Option<(&'a str, usize)> {
+        let source = self.source;
+        let start = self.skip_spaces(start);
+        if !source[start..].starts_with("**") {
+            return None;
+        }
+
+        let start = self.skip_spaces(start + 2);
+        let digits = source[start..]
+            .strip_prefix('-')
+            .map_or(start, |_| start + 1);
+        let end = source[digits..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(source.len(), |len| digits + len);
+
+        let rest = &source[end..];
+        let tighter = rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            || rest.starts_with("**");
+        if end == digits || tighter {
+            None
+        } else {
+            Some((&source[start..end], end))
+        }
+    }
+
+    /// Offset of the first char from `from` that is not a space, on the same line.
+    fn skip_spaces(&self, from: usize) -> usize {
+        self.source.len()
+            - self.source[from..]
+                .trim_start_matches(|c: char| c.is_whitespace() && c != '\n')
+                .len()
+    }
+
+    /// Whether an operand starts at the offset `start`, spaces on the same line skipped.
+    /// A sign directly followed by an operand starts it, like after `*` or `/`: `7 % -3`
+    /// is a modulo while `10% - 2` is a percentage.
+    fn operand_after(&self, start: usize) -> bool {
+        let rest =
+            self.source[start..].trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+        let word = rest
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default();
 
-    let mut first_index = 0;
-    for window in tokens.windows(2) {
-        let first = window[0];
-        let second = window[1];
+        rest.starts_with(|c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '('))
+            && !matches!(word, "in" | "to" | "and" | "or")
+    }
 
-        if first.is_atom() || first == Token::ParenEnd {
-            if second.is_atom() || second == Token::ParenStart {
-                insert_indices.push(first_index + 1);
+    /// Skip the chars before the offset `end`.
+    fn advance_to(&mut self, end: usize) {
+        while let Some(&(offset, _)) = self.chars.peek() {
+            if offset >= end {
+                break;
             }
+            self.chars.next();
         }
+    }
 
-        first_index += 1;
+    /// Read the word in `span` according to the following grammar:
+    /// ```bnf
+    /// <word>   ::= <letter> [ ( <letter> | "0" .. "9" ) + ]
+    /// <letter> ::= "a" .. "z" | "A" .. "Z" | "_"
+    /// ```
+    /// Words are read greedily, so `pie` is a single identifier and not `pi * e`.
+    /// The words `e` and `pi` are the constants, `i` is the imaginary unit, `in` and
+    /// `to` convert to a unit, `and`, `or` and `not` are the logical operators and every
+    /// other word is an identifier.
+    fn read_word(&self, span: Span) -> Token<N> {
+        match &self.source[span.start..span.end] {
+            "e" => Token::E,
+            "pi" => Token::Pi,
+            "i" => Token::I,
+            "in" | "to" => Token::In,
+            "and" => Token::And,
+            "or" => Token::Or,
+            "not" => Token::Not,
+            word => Token::Ident(word.into()),
+        }
     }
 
-    for i in insert_indices.into_iter().rev() {
-        tokens.insert(i, Token::Times);
+    /// Remember what `token` allows to follow it before producing it.
+    fn produce(&mut self, token: Spanned<Token<N>>) -> Option<Result<Spanned<Token<N>>>> {
+        self.previous = match token.value {
+            Token::Ident(_) => Previous::Ident,
+            Token::ParenEnd | Token::PostfixPercent | Token::Bang => Previous::Operand,
+            ref value if value.is_atom() => Previous::Operand,
+            _ => Previous::Operator,
+        };
+        self.end = token.span.end;
+        self.in_statement = token.value != Token::Separator;
+
+        Some(Ok(token))
     }
+}
 
-    tokens
+impl<'a, N: Numeric> Iterator for Lexer<'a, N> {
+    type Item = Result<Spanned<Token<N>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.take() {
+            return self.produce(token);
+        }
+        if self.failed {
+            return None;
+        }
+
+        let token = match self.read()? {
+            Ok(token) => token,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+
+        let implicit_mul = match self.previous {
+            Previous::Operator => false,
+            Previous::Operand => token.value.is_atom() || token.value == Token::ParenStart,
+            Previous::Ident => token.value.is_atom(),
+        };
+
+        if implicit_mul {
+            let gap = Span::new(self.end, token.span.start);
+            self.pending = Some(token);
+            self.produce(Spanned::new(Token::Times, gap))
+        } else {
+            self.produce(token)
+        }
+    }
+}
+
+fn is_digit(byte: Option<&u8>, radix: u32) -> bool {
+    byte.is_some_and(|&byte| (byte as char).is_digit(radix))
+}
+
+/// Offset of the end of the digits starting at `start`, a `_` between two digits is a
+/// separator and any other `_` directly before or after a digit is an error.
+fn skip_digits(bytes: &[u8], start: usize, radix: u32) -> Result<usize> {
+    let mut end = start;
+    loop {
+        while is_digit(bytes.get(end), radix) {
+            end += 1;
+        }
+
+        let underscores = bytes[end..]
+            .iter()
+            .take_while(|&&byte| byte == b'_')
+            .count();
+        if underscores == 0 {
+            return Ok(end);
+        } else if end == start || underscores > 1 || !is_digit(bytes.get(end + 1), radix) {
+            return Err(ParserError::MisplacedUnderscore(Span::new(
+                end,
+                end + underscores,
+            )));
+        }
+        end += 1;
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::token::{tokenize, Token};
+    use crate::errors::{ParserError, Result};
+    use crate::span::Span;
+    use crate::token::{tokenize, Lexer, Token};
+
+    /// Tokenize and discard the spans.
+    fn tokenize_bare(source: &str) -> Result<Vec<Token>> {
+        Ok(tokenize(source)?.into_iter().map(|t| t.value).collect())
+    }
 
     #[test]
     fn tokenize_numbers() {
-        let tokens = tokenize("012.345".into()).unwrap();
+        let tokens = tokenize_bare("012.345").unwrap();
         assert_eq!(tokens, vec![Token::Number(12.345)]);
 
-        let tokens = tokenize("pie".into()).unwrap();
+        let tokens = tokenize_bare("pi e").unwrap();
         // Note: implicit mul kicks in
         assert_eq!(tokens, vec![Token::Pi, Token::Times, Token::E,]);
 
-        let tokens = tokenize("12".into()).unwrap();
+        let tokens = tokenize_bare("12").unwrap();
         assert_eq!(tokens, vec![Token::Number(12.0)]);
 
-        let tokens = tokenize("12.".into()).unwrap();
+        let tokens = tokenize_bare("12.").unwrap();
         assert_eq!(tokens, vec![Token::Number(12.0)]);
 
-        let tokens = tokenize(".4".into()).unwrap();
+        let tokens = tokenize_bare(".4").unwrap();
         assert_eq!(tokens, vec![Token::Number(0.4)]);
 
-        assert!(tokenize(".".into()).is_err());
+        assert!(tokenize_bare(".").is_err());
+    }
+
+    #[test]
+    fn tokenize_literals() {
+        let numbers = |source: &str| {
+            tokenize_bare(source)
+                .unwrap()
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Number(num) => Some(num),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(numbers("6.022e23"), vec![6.022e23]);
+        assert_eq!(
+            numbers("1e-9 2.5E+3 .5e1 1.e2"),
+            vec![1e-9, 2500., 5., 100.]
+        );
+        assert_eq!(numbers("0xff 0x1F 0o17 0b1010"), vec![255., 31., 15., 10.]);
+        assert_eq!(
+            numbers("1_000_000 0xff_ff 1_0.2_5e1_0"),
+            vec![1e6, 65535., 10.25e10]
+        );
+
+        // Not followed by a digit, `e` is the constant and the prefixes are multiplied
+        assert_eq!(
+            tokenize_bare("2e + 2e+").unwrap(),
+            vec![
+                Token::Number(2.),
+                Token::Times,
+                Token::E,
+                Token::Plus,
+                Token::Number(2.),
+                Token::Times,
+                Token::E,
+                Token::Plus,
+            ]
+        );
+        assert_eq!(
+            tokenize_bare("2E").unwrap(),
+            vec![Token::Number(2.), Token::Times, Token::Ident("E".into())]
+        );
+        assert_eq!(
+            tokenize_bare("0x 0b2").unwrap(),
+            vec![
+                Token::Number(0.),
+                Token::Times,
+                Token::Ident("x".into()),
+                Token::Times,
+                Token::Number(0.),
+                Token::Times,
+                Token::Ident("b2".into()),
+            ]
+        );
+
+        // A separator is only between two digits
+        for (source, span) in [
+            ("12_", Span::new(2, 3)),
+            ("1__0", Span::new(1, 3)),
+            ("0x_1", Span::new(2, 3)),
+            ("0b1_", Span::new(3, 4)),
+            ("1_.5", Span::new(1, 2)),
+            ("1._5", Span::new(2, 3)),
+            ("1e5_", Span::new(3, 4)),
+            ("2 * 1_x", Span::new(5, 6)),
+        ] {
+            match tokenize(source) {
+                Err(ParserError::MisplacedUnderscore(error)) => assert_eq!(error, span),
+                other => panic!("unexpected result for {}: {:?}", source, other),
+            }
+        }
+        assert_eq!(
+            tokenize_bare("x_1 _2").unwrap(),
+            vec![
+                Token::Ident("x_1".into()),
+                Token::Times,
+                Token::Ident("_2".into()),
+            ]
+        );
     }
 
     #[test]
     fn tokenize_operators() {
-        let tokens = tokenize("+2+-1-*/***".into()).unwrap();
+        let tokens = tokenize_bare("+2+-1-*/***").unwrap();
         assert_eq!(
             tokens,
             vec![
@@ -307,21 +752,213 @@ mod tests {
         )
     }
 
+    #[test]
+    fn tokenize_logic() {
+        let tokens = tokenize_bare("a==b!=c<d<=-e>f>=g ? not 1 : 2 and x or y").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("a".into()),
+                Token::EqualEqual,
+                Token::Ident("b".into()),
+                Token::NotEqual,
+                Token::Ident("c".into()),
+                Token::Less,
+                Token::Ident("d".into()),
+                Token::LessEqual,
+                Token::UnaryMinus,
+                Token::E,
+                Token::Greater,
+                Token::Ident("f".into()),
+                Token::GreaterEqual,
+                Token::Ident("g".into()),
+                Token::Question,
+                Token::Not,
+                Token::Number(1.),
+                Token::Colon,
+                Token::Number(2.),
+                Token::And,
+                Token::Ident("x".into()),
+                Token::Or,
+                Token::Ident("y".into()),
+            ]
+        );
+        assert!(tokenize_bare("1 # 2").is_err());
+    }
+
+    #[test]
+    fn tokenize_postfix() {
+        let tokens = tokenize_bare("7 % 3 // 2 + 15% - 10%(x)!3!!").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(7.),
+                Token::Percent,
+                Token::Number(3.),
+                Token::SlashSlash,
+                Token::Number(2.),
+                Token::Plus,
+                Token::Number(15.),
+                Token::PostfixPercent,
+                Token::Minus,
+                Token::Number(10.),
+                Token::Percent,
+                Token::ParenStart,
+                Token::Ident("x".into()),
+                Token::ParenEnd,
+                Token::Bang,
+                Token::Times,
+                Token::Number(3.),
+                Token::Bang,
+                Token::Bang,
+            ]
+        );
+
+        // A sign directly followed by an operand is unary, like after `*`, otherwise
+        // it is a binary operator after a percentage
+        assert_eq!(
+            tokenize_bare("n!=1% -2").unwrap(),
+            vec![
+                Token::Ident("n".into()),
+                Token::NotEqual,
+                Token::Number(1.),
+                Token::Percent,
+                Token::UnaryMinus,
+                Token::Number(2.),
+            ]
+        );
+        assert_eq!(
+            tokenize_bare("1%+x 1% - 2").unwrap(),
+            vec![
+                Token::Number(1.),
+                Token::Percent,
+                Token::UnaryPlus,
+                Token::Ident("x".into()),
+                Token::Times,
+                Token::Number(1.),
+                Token::PostfixPercent,
+                Token::Minus,
+                Token::Number(2.),
+            ]
+        );
+        assert_eq!(
+            tokenize_bare("1% in x").unwrap()[1..3],
+            [Token::PostfixPercent, Token::In]
+        );
+    }
+
     #[test]
     fn tokenize_other() {
-        let tokens = tokenize(" \n\t".into()).unwrap();
+        let tokens = tokenize_bare(" \n\t").unwrap();
         assert_eq!(tokens, vec![]);
+
+        // Newlines separate statements
+        assert_eq!(
+            tokenize_bare("x = 1;\n\n-x").unwrap(),
+            vec![
+                Token::Ident("x".into()),
+                Token::Equal,
+                Token::Number(1.),
+                Token::Separator,
+                Token::UnaryMinus,
+                Token::Ident("x".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize_bare("2 m\nm").unwrap(),
+            vec![
+                Token::Quantity(2., "m".into()),
+                Token::Separator,
+                Token::Ident("m".into()),
+            ]
+        );
+        // But not inside parentheses
+        assert_eq!(
+            tokenize_bare("max(1,\n2)\n").unwrap(),
+            vec![
+                Token::Ident("max".into()),
+                Token::ParenStart,
+                Token::Number(1.),
+                Token::Comma,
+                Token::Number(2.),
+                Token::ParenEnd,
+                Token::Separator,
+            ]
+        );
     }
 
     #[test]
     fn tokenize_fail() {
-        assert!(tokenize("abc".into()).is_err());
-        assert!(tokenize("%".into()).is_err());
+        assert!(tokenize_bare("a$c").is_err());
+        assert!(tokenize_bare("a@c").is_err());
+    }
+
+    #[test]
+    fn tokenize_identifiers() {
+        let tokens = tokenize_bare("x + rate_2 * _").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("x".into()),
+                Token::Plus,
+                Token::Ident("rate_2".into()),
+                Token::Times,
+                Token::Ident("_".into()),
+            ]
+        );
+
+        // Words are read greedily
+        let tokens = tokenize_bare("pie").unwrap();
+        assert_eq!(tokens, vec![Token::Ident("pie".into())]);
+
+        // `i` is the imaginary unit
+        let tokens = tokenize_bare("2i + ix").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.),
+                Token::Times,
+                Token::I,
+                Token::Plus,
+                Token::Ident("ix".into()),
+            ]
+        );
+
+        let tokens = tokenize_bare("2x y").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.),
+                Token::Times,
+                Token::Ident("x".into()),
+                Token::Times,
+                Token::Ident("y".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_calls() {
+        let tokens = tokenize_bare("2max(x, -1)").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.),
+                Token::Times,
+                Token::Ident("max".into()),
+                Token::ParenStart,
+                Token::Ident("x".into()),
+                Token::Comma,
+                Token::UnaryMinus,
+                Token::Number(1.),
+                Token::ParenEnd,
+            ]
+        );
     }
 
     #[test]
     fn implicit_mul() {
-        let tokens = tokenize("1(2)".into()).unwrap();
+        let tokens = tokenize_bare("1(2)").unwrap();
         assert_eq!(
             tokens,
             vec![
@@ -333,7 +970,7 @@ mod tests {
             ]
         );
 
-        let tokens = tokenize("(1)(2)".into()).unwrap();
+        let tokens = tokenize_bare("(1)(2)").unwrap();
         assert_eq!(
             tokens,
             vec![
@@ -347,10 +984,10 @@ mod tests {
             ]
         );
 
-        let tokens = tokenize("1pi".into()).unwrap();
+        let tokens = tokenize_bare("1pi").unwrap();
         assert_eq!(tokens, vec![Token::Number(1.), Token::Times, Token::Pi,]);
 
-        let tokens = tokenize("(1)2".into()).unwrap();
+        let tokens = tokenize_bare("(1)2").unwrap();
         assert_eq!(
             tokens,
             vec![
@@ -365,7 +1002,7 @@ mod tests {
 
     #[test]
     fn implicit_mul_in_the_wild() {
-        let tokens = tokenize("(1+2)(1-2)(2pi/4)".into()).unwrap();
+        let tokens = tokenize_bare("(1+2)(1-2)(2pi/4)").unwrap();
         assert_eq!(
             tokens,
             vec![
@@ -391,4 +1028,147 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn lexer_iterates_lazily() {
+        let mut lexer = Lexer::<f64>::new("2x $ 1");
+        assert_eq!(lexer.next().unwrap().unwrap().value, Token::Number(2.));
+        assert_eq!(lexer.next().unwrap().unwrap().value, Token::Times);
+        assert_eq!(
+            lexer.next().unwrap().unwrap().value,
+            Token::Ident("x".into())
+        );
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(ParserError::UnexpectedChar('$', _)))
+        ));
+        // Nothing is produced after an error
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn tokenize_unit_powers() {
+        assert_eq!(
+            tokenize_bare("3 m**2 + 1 s ** -1").unwrap(),
+            vec![
+                Token::Quantity(3., "m**2".into()),
+                Token::Plus,
+                Token::Quantity(1., "s**-1".into()),
+            ]
+        );
+
+        // Only integer powers of the unit alone
+        assert_eq!(
+            tokenize_bare("3 m**2.5").unwrap(),
+            vec![
+                Token::Quantity(3., "m".into()),
+                Token::TimesTimes,
+                Token::Number(2.5),
+            ]
+        );
+        assert_eq!(
+            tokenize_bare("3 m**x").unwrap(),
+            vec![
+                Token::Quantity(3., "m".into()),
+                Token::TimesTimes,
+                Token::Ident("x".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_units() {
+        assert_eq!(
+            tokenize_bare("3 km/h to m/s").unwrap(),
+            vec![
+                Token::Quantity(3., "km/h".into()),
+                Token::In,
+                Token::Ident("m".into()),
+                Token::Slash,
+                Token::Ident("s".into()),
+            ]
+        );
+
+        assert_eq!(
+            tokenize_bare("1 kg*m/s**2 / 2 s").unwrap(),
+            vec![
+                Token::Quantity(1., "kg*m/s**2".into()),
+                Token::Slash,
+                Token::Quantity(2., "s".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize_bare("3 m / h").unwrap(),
+            vec![
+                Token::Quantity(3., "m".into()),
+                Token::Slash,
+                Token::Ident("h".into()),
+            ]
+        );
+
+        // Only known units followed by anything but a call
+        assert_eq!(
+            tokenize_bare("2 min(1) 2x").unwrap(),
+            vec![
+                Token::Number(2.),
+                Token::Times,
+                Token::Ident("min".into()),
+                Token::ParenStart,
+                Token::Number(1.),
+                Token::ParenEnd,
+                Token::Times,
+                Token::Number(2.),
+                Token::Times,
+                Token::Ident("x".into()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_layout() {
+        let tokens = tokenize_bare("2x").unwrap();
+        let json = serde_json::json!([
+            { "type": "number", "value": 2.0 },
+            { "type": "times" },
+            { "type": "ident", "value": "x" },
+        ]);
+
+        assert_eq!(serde_json::to_value(&tokens).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Vec<Token>>(json).unwrap(), tokens);
+    }
+
+    #[test]
+    fn token_spans() {
+        let spans = tokenize("12.5 ** pi(1)")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.span)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 4),
+                Span::new(5, 7),
+                Span::new(8, 10),
+                Span::new(10, 10),
+                Span::new(10, 11),
+                Span::new(11, 12),
+                Span::new(12, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_error_spans() {
+        match tokenize("1 + $") {
+            Err(ParserError::UnexpectedChar('$', span)) => assert_eq!(span, Span::new(4, 5)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match tokenize("1+.") {
+            Err(ParserError::LoneDot(span)) => assert_eq!(span, Span::new(2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
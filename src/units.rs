@@ -0,0 +1,468 @@
+//! # Units module
+//! Contains the physical [Dimension]s, the [Quantity] an expression evaluates to and
+//! the table of the known [Unit]s.
+//!
+//! A number directly followed by the name of a unit, like `3 km`, is a quantity of this
+//! unit, and an integer power applies to the unit alone: `3 m**2` is 3 square meters,
+//! unlike `(3 m)**2`. Units joined by `*` or `/` without spaces are a single one, so
+//! `3 km/h` is a speed. Elsewhere names are variables, except in the target of a
+//! conversion. Quantities are computed in the SI base units and
+//! converted with `in` or `to`, the operator with the lowest precedence: `3 km/20 min
+//! in m/s` is `2.5 m/s`.
+//!
+//! Adding or comparing quantities of different dimensions is an error, as is passing
+//! a quantity with a unit to a function other than `abs`, `sqrt`, `cbrt`, `min`, `max`
+//! and the rounding functions. Only units proportional to the SI ones are supported,
+//! so temperatures are in kelvins.
+//!
+//! ## Example
+//! ```rust
+//! # fn main() {
+//! # use rust_calculator::eval::Eval;
+//! # use rust_calculator::parser::Parser;
+//! # use rust_calculator::token::tokenize;
+//! let expr = Parser::new(tokenize("3 km / 20 min in km/h").unwrap()).parse().unwrap();
+//!
+//! assert_eq!(expr.try_eval_quantity().unwrap().to_string(), "9 km/h");
+//! # }
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::ops::{Div, Mul};
+
+use crate::ast::Number;
+use crate::errors::{EvalError, EvalResult};
+use crate::numeric::Numeric;
+
+/// Symbols of the SI base units, in the order of the exponents of a [Dimension].
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// Exponents of the SI base units: length, mass, time, electric current, temperature,
+/// amount of substance and luminous intensity.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct Dimension(pub [i8; 7]);
+
+impl Dimension {
+    pub const NONE: Self = Self([0; 7]);
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Dimension raised to `exponent`, `None` unless every exponent stays an integer.
+    pub fn pow(self, exponent: f64) -> Option<Self> {
+        let mut result = Self::NONE;
+        for (power, base) in result.0.iter_mut().zip(&self.0) {
+            let scaled = *base as f64 * exponent;
+            if scaled.fract() != 0. || scaled.abs() > i8::MAX as f64 {
+                return None;
+            }
+            *power = scaled as i8;
+        }
+
+        Some(result)
+    }
+
+    fn combine(self, other: Self, op: impl Fn(i8, i8) -> Option<i8>) -> Option<Self> {
+        let mut result = self;
+        for (power, other) in result.0.iter_mut().zip(&other.0) {
+            *power = op(*power, *other)?;
+        }
+        Some(result)
+    }
+}
+
+/// Dimension of a product, `None` if an exponent overflows.
+impl Mul for Dimension {
+    type Output = Option<Self>;
+
+    fn mul(self, other: Self) -> Option<Self> {
+        self.combine(other, i8::checked_add)
+    }
+}
+
+/// Dimension of a quotient, `None` if an exponent overflows.
+impl Div for Dimension {
+    type Output = Option<Self>;
+
+    fn div(self, other: Self) -> Option<Self> {
+        self.combine(other, i8::checked_sub)
+    }
+}
+
+impl Display for Dimension {
+    /// Formatted with the SI base units like `kg*m/s**2`, or `dimensionless`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_none() {
+            return write!(f, "dimensionless");
+        }
+
+        let factors = |positive: bool| {
+            BASE_UNITS
+                .iter()
+                .zip(&self.0)
+                .filter(|(_, &power)| power != 0 && (power > 0) == positive)
+                .map(|(symbol, &power)| match power.abs() {
+                    1 => symbol.to_string(),
+                    power => format!("{}**{}", symbol, power),
+                })
+                .collect::<Vec<_>>()
+        };
+        let (numer, denom) = (factors(true), factors(false));
+
+        match (numer.len(), denom.len()) {
+            (0, _) => write!(f, "1")?,
+            _ => write!(f, "{}", numer.join("*"))?,
+        }
+        match denom.len() {
+            0 => Ok(()),
+            1 => write!(f, "/{}", denom[0]),
+            _ => write!(f, "/({})", denom.join("*")),
+        }
+    }
+}
+
+/// A unit of the table.
+#[derive(Copy, Clone, Debug)]
+pub struct Unit {
+    pub name: &'static str,
+    /// Value in the SI base units, as a literal to stay exact with every [Numeric].
+    pub factor: &'static str,
+    pub dimension: Dimension,
+}
+
+macro_rules! unit {
+    ($name:literal, $factor:literal, [$($power:expr),*]) => {
+        Unit {
+            name: $name,
+            factor: $factor,
+            dimension: Dimension([$($power),*]),
+        }
+    };
+}
+
+/// The known units, SI and common imperial ones.
+pub const UNITS: &[Unit] = &[
+    // Length
+    unit!("m", "1", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("km", "1e3", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("cm", "1e-2", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("mm", "1e-3", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("um", "1e-6", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("nm", "1e-9", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("inch", "0.0254", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("ft", "0.3048", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("yd", "0.9144", [1, 0, 0, 0, 0, 0, 0]),
+    unit!("mi", "1609.344", [1, 0, 0, 0, 0, 0, 0]),
+    // Area and volume
+    unit!("ha", "1e4", [2, 0, 0, 0, 0, 0, 0]),
+    unit!("acre", "4046.8564224", [2, 0, 0, 0, 0, 0, 0]),
+    unit!("L", "1e-3", [3, 0, 0, 0, 0, 0, 0]),
+    unit!("mL", "1e-6", [3, 0, 0, 0, 0, 0, 0]),
+    unit!("gal", "3.785411784e-3", [3, 0, 0, 0, 0, 0, 0]),
+    // Mass
+    unit!("kg", "1", [0, 1, 0, 0, 0, 0, 0]),
+    unit!("g", "1e-3", [0, 1, 0, 0, 0, 0, 0]),
+    unit!("mg", "1e-6", [0, 1, 0, 0, 0, 0, 0]),
+    unit!("t", "1e3", [0, 1, 0, 0, 0, 0, 0]),
+    unit!("lb", "0.45359237", [0, 1, 0, 0, 0, 0, 0]),
+    unit!("oz", "0.028349523125", [0, 1, 0, 0, 0, 0, 0]),
+    // Time
+    unit!("s", "1", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("ms", "1e-3", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("us", "1e-6", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("ns", "1e-9", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("min", "60", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("h", "3600", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("day", "86400", [0, 0, 1, 0, 0, 0, 0]),
+    unit!("Hz", "1", [0, 0, -1, 0, 0, 0, 0]),
+    // Speed
+    unit!("mph", "0.44704", [1, 0, -1, 0, 0, 0, 0]),
+    // Force and pressure
+    unit!("N", "1", [1, 1, -2, 0, 0, 0, 0]),
+    unit!("kN", "1e3", [1, 1, -2, 0, 0, 0, 0]),
+    unit!("lbf", "4.4482216152605", [1, 1, -2, 0, 0, 0, 0]),
+    unit!("Pa", "1", [-1, 1, -2, 0, 0, 0, 0]),
+    unit!("kPa", "1e3", [-1, 1, -2, 0, 0, 0, 0]),
+    unit!("bar", "1e5", [-1, 1, -2, 0, 0, 0, 0]),
+    unit!("atm", "101325", [-1, 1, -2, 0, 0, 0, 0]),
+    unit!("psi", "6894.757293168361", [-1, 1, -2, 0, 0, 0, 0]),
+    // Energy and power
+    unit!("J", "1", [2, 1, -2, 0, 0, 0, 0]),
+    unit!("kJ", "1e3", [2, 1, -2, 0, 0, 0, 0]),
+    unit!("cal", "4.184", [2, 1, -2, 0, 0, 0, 0]),
+    unit!("kcal", "4184", [2, 1, -2, 0, 0, 0, 0]),
+    unit!("Wh", "3600", [2, 1, -2, 0, 0, 0, 0]),
+    unit!("kWh", "3.6e6", [2, 1, -2, 0, 0, 0, 0]),
+    unit!("W", "1", [2, 1, -3, 0, 0, 0, 0]),
+    unit!("kW", "1e3", [2, 1, -3, 0, 0, 0, 0]),
+    unit!("hp", "745.69987158227022", [2, 1, -3, 0, 0, 0, 0]),
+    // Electricity
+    unit!("A", "1", [0, 0, 0, 1, 0, 0, 0]),
+    unit!("mA", "1e-3", [0, 0, 0, 1, 0, 0, 0]),
+    unit!("C", "1", [0, 0, 1, 1, 0, 0, 0]),
+    unit!("V", "1", [2, 1, -3, -1, 0, 0, 0]),
+    unit!("ohm", "1", [2, 1, -3, -2, 0, 0, 0]),
+    // Other base units
+    unit!("K", "1", [0, 0, 0, 0, 1, 0, 0]),
+    unit!("mol", "1", [0, 0, 0, 0, 0, 1, 0]),
+    unit!("cd", "1", [0, 0, 0, 0, 0, 0, 1]),
+];
+
+/// Find a unit of the table by name.
+pub fn unit(name: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|unit| unit.name == name)
+}
+
+/// The quantity `1 name`, where `name` is a unit of the table or a product of units
+/// raised to integer powers, like `m**2` or `kg*m/s**2`.
+pub fn unit_quantity<N: Numeric>(name: &str) -> EvalResult<Quantity<N>> {
+    let unknown = || EvalError::UnknownUnit(name.into());
+    let powers = name.replace("**", "^");
+
+    let mut value = N::from_literal("1");
+    let mut dimension = Dimension::NONE;
+    let mut divide = false;
+    // Each factor ends with the operator applied to the next one
+    for factor in powers.split_inclusive(['*', '/']) {
+        let (factor, next) = match factor.strip_suffix(['*', '/']) {
+            Some(stripped) => (stripped, factor.ends_with('/')),
+            None => (factor, false),
+        };
+        let (base, exponent) = match factor.split_once('^') {
+            Some((base, exponent)) => (base, exponent.parse::<i8>().map_err(|_| unknown())?),
+            None => (factor, 1),
+        };
+        let unit = unit(base).ok_or_else(unknown)?;
+
+        let power = unit
+            .dimension
+            .pow(exponent as f64)
+            .ok_or(EvalError::Overflow)?;
+        let factor = N::from_literal(unit.factor)
+            .pow(&N::from_literal(&exponent.unsigned_abs().to_string()));
+        if divide == (exponent < 0) {
+            value = value * factor;
+        } else {
+            value = value / factor;
+        }
+        dimension = if divide {
+            dimension / power
+        } else {
+            dimension * power
+        }
+        .ok_or(EvalError::Overflow)?;
+        divide = next;
+    }
+
+    Ok(Quantity::in_si(value, dimension))
+}
+
+/// The value of an expression with its dimension.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Quantity<N = Number> {
+    /// Value in `unit`, or in the SI base units of the dimension.
+    pub value: N,
+    pub dimension: Dimension,
+    /// Name of the unit the quantity was converted to, with its value in the SI base
+    /// units.
+    pub unit: Option<(String, N)>,
+}
+
+impl<N: Numeric> Quantity<N> {
+    /// A dimensionless quantity.
+    pub fn new(value: N) -> Self {
+        Self::in_si(value, Dimension::NONE)
+    }
+
+    /// A quantity of `value` SI base units of `dimension`.
+    pub fn in_si(value: N, dimension: Dimension) -> Self {
+        Self {
+            value,
+            dimension,
+            unit: None,
+        }
+    }
+
+    /// Value in the SI base units.
+    pub fn si_value(&self) -> N {
+        match &self.unit {
+            Some((_, factor)) => self.value.clone() * factor.clone(),
+            None => self.value.clone(),
+        }
+    }
+
+    /// Name of the unit of `value`, `None` if dimensionless.
+    pub fn unit_name(&self) -> Option<String> {
+        match &self.unit {
+            Some((name, _)) => Some(name.clone()),
+            None if self.dimension.is_none() => None,
+            None => Some(self.dimension.to_string()),
+        }
+    }
+
+    /// Fail unless the quantity has the given dimension.
+    pub fn expect(&self, dimension: Dimension) -> EvalResult<()> {
+        if self.dimension == dimension {
+            Ok(())
+        } else {
+            Err(EvalError::IncompatibleUnits {
+                expected: dimension,
+                got: self.dimension,
+            })
+        }
+    }
+}
+
+impl<N: Numeric> Display for Quantity<N> {
+    /// Formatted as the value followed by its unit, if any.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.unit_name() {
+            Some(name) => write!(f, "{} {}", self.value, name),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// Dimension of the result of the function `name` applied to arguments of the given
+/// dimensions.
+pub fn call_dimension(name: &str, args: &[Dimension]) -> EvalResult<Dimension> {
+    let first = args.first().copied().unwrap_or_default();
+    let same = |expected: Dimension| match args.iter().find(|&&dimension| dimension != expected) {
+        Some(&got) => Err(EvalError::IncompatibleUnits { expected, got }),
+        None => Ok(expected),
+    };
+
+    match name {
+        "abs" | "floor" | "ceil" | "round" | "trunc" | "min" | "max" => same(first),
+        "sqrt" | "cbrt" => {
+            let root = if name == "sqrt" { 2. } else { 3. };
+            first.pow(1. / root).ok_or(EvalError::IncompatibleUnits {
+                expected: Dimension::NONE,
+                got: first,
+            })
+        }
+        _ => same(Dimension::NONE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::EvalError;
+    use crate::eval::{Environment, Eval};
+    use crate::parser::Parser;
+    use crate::rational::Rational;
+    use crate::token::{tokenize, tokenize_as};
+    use crate::units::Dimension;
+
+    fn eval(source: &str) -> Result<String, EvalError> {
+        Parser::new(tokenize(source).unwrap())
+            .parse()
+            .unwrap()
+            .try_eval_quantity()
+            .map(|quantity| quantity.to_string())
+    }
+
+    #[test]
+    fn dimension_display() {
+        assert_eq!(Dimension::NONE.to_string(), "dimensionless");
+        assert_eq!(Dimension([1, 0, -1, 0, 0, 0, 0]).to_string(), "m/s");
+        assert_eq!(Dimension([1, 1, -2, 0, 0, 0, 0]).to_string(), "m*kg/s**2");
+        assert_eq!(Dimension([0, 0, -1, 0, -1, 0, 0]).to_string(), "1/(s*K)");
+    }
+
+    #[test]
+    fn quantities() {
+        assert_eq!(eval("3 km / 20 min"), Ok("2.5 m/s".into()));
+        assert_eq!(eval("3 km/20 min in m/s"), Ok("2.5 m/s".into()));
+        assert_eq!(eval("60 mph to km/h").unwrap()[..6], *"96.560");
+        assert_eq!(eval("(1 + 2) * 1 km + 500m in km"), Ok("3.5 km".into()));
+        assert_eq!(eval("2 kg * 9.81 m/s**2 in N"), Ok("19.62 N".into()));
+        assert_eq!(eval("sqrt(16 m**2)"), Ok("4 m".into()));
+        assert_eq!(eval("3 m**2"), Ok("3 m**2".into()));
+        assert_eq!(eval("(3 m)**2"), Ok("9 m**2".into()));
+        assert_eq!(eval("100 m**2 in ha"), Ok("0.01 ha".into()));
+        assert_eq!(eval("2 km ** -1"), Ok("0.002 1/m".into()));
+        assert_eq!(eval("1 h / 1 min"), Ok("60".into()));
+        assert_eq!(eval("2x"), Err(EvalError::UnboundVariable("x".into())));
+    }
+
+    #[test]
+    fn units_only_in_literals() {
+        assert_eq!(eval("1 kg*m/s**2 in N"), Ok("1 N".into()));
+        assert_eq!(eval("36 km/h in m/s"), Ok("10 m/s".into()));
+        assert_eq!(eval("2 Hz to 1/s"), Ok("2 1/s".into()));
+        assert_eq!(eval("1 km**2 in m**2"), Ok("1000000 m**2".into()));
+        assert_eq!(eval("3 m / h"), Err(EvalError::UnboundVariable("h".into())));
+
+        // Bound or not, names outside the literals and the targets are variables
+        let env = vec![("x", 2.), ("s", 2.)]
+            .into_iter()
+            .collect::<Environment>();
+        let eval = |source: &str| {
+            Parser::new(tokenize(source).unwrap())
+                .parse()
+                .unwrap()
+                .eval_quantity_in(&env)
+                .map(|quantity| quantity.to_string())
+        };
+        assert_eq!(
+            eval("3 * x**2 * h"),
+            Err(EvalError::UnboundVariable("h".into()))
+        );
+        assert_eq!(eval("4 m / s"), Ok("2 m".into()));
+        assert_eq!(eval("3 m/s in km/s"), Ok("0.003 km/s".into()));
+    }
+
+    #[test]
+    fn exact_conversions() {
+        let expr = Parser::new(tokenize_as::<Rational>("1 mi in ft").unwrap())
+            .parse()
+            .unwrap();
+        assert_eq!(expr.try_eval_quantity().unwrap().to_string(), "5280 ft");
+    }
+
+    #[test]
+    fn incompatible_units() {
+        let length = Dimension([1, 0, 0, 0, 0, 0, 0]);
+        let time = Dimension([0, 0, 1, 0, 0, 0, 0]);
+
+        assert_eq!(
+            eval("1 m + 1 s"),
+            Err(EvalError::IncompatibleUnits {
+                expected: length,
+                got: time
+            })
+        );
+        assert_eq!(
+            eval("1 m in s").unwrap_err().to_string(),
+            "Incompatible units, expected s but got m"
+        );
+        assert_eq!(
+            eval("sin(1 m)").unwrap_err().to_string(),
+            "Incompatible units, expected dimensionless but got m"
+        );
+        assert!(eval("sqrt(2 m)").is_err());
+        assert!(eval("2 ** (1 s)").is_err());
+        assert_eq!(eval("1 in x"), Err(EvalError::UnknownUnit("x".into())));
+        assert_eq!(eval("1 km in 2 m"), Err(EvalError::NotAUnit("2 m".into())));
+        assert_eq!(
+            eval("1 km in m + 1"),
+            Err(EvalError::NotAUnit("m + 1".into()))
+        );
+        assert_eq!(
+            eval("1 km in m**x"),
+            Err(EvalError::NotAUnit("m ** x".into()))
+        );
+    }
+
+    #[test]
+    fn dimension_overflow() {
+        assert_eq!(
+            Dimension([100, 0, 0, 0, 0, 0, 0]) * Dimension([100, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+        assert_eq!(eval("(1 m)**100 * (1 m)**100"), Err(EvalError::Overflow));
+        assert_eq!(eval("(1 m)**100 / (1 m)**-100"), Err(EvalError::Overflow));
+    }
+}